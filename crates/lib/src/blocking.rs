@@ -0,0 +1,13 @@
+//! Blocking clients, for use from synchronous code without an async runtime.
+//!
+//! These mirror the async [`FixtureClient`][crate::FixtureClient] and [`TestClient`][crate::TestClient],
+//! but use blocking I/O on a standard library socket.
+//! The [`with_fixture`][crate::with_fixture] macro uses the blocking [`TestClient`] when applied to a non-async function.
+//!
+//! Available with the `blocking` feature.
+
+mod client_fixture;
+mod client_test;
+
+pub use client_fixture::FixtureClient;
+pub use client_test::TestClient;
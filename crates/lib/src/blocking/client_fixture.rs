@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{
+    rpc_socket::{
+        blocking::RpcSocket, hello, path_to_string, set_env_requests, Capability, ConnectionType,
+        EnvOp, EnvTarget, Request,
+    },
+    Error, Result,
+};
+
+/// A blocking RPC client used from fixture code.
+///
+/// An instance is created using [`FixtureClient::connect()`].
+///
+/// All methods take `&self`, the client can be shared between threads (e.g. using an `Arc`)
+/// and requests can be made concurrently.
+pub struct FixtureClient {
+    socket: RpcSocket,
+}
+
+impl FixtureClient {
+    /// Connect to the parent `cargo fixture` process.
+    pub fn connect() -> Result<Self> {
+        RpcSocket::connect(hello(ConnectionType::Fixture)).map(|socket| Self { socket })
+    }
+
+    /// Request that an environment variable be set for `cargo test`.
+    pub fn set_env_var(&self, name: impl Into<String>, value: impl Into<String>) -> Result<()> {
+        let name = name.into();
+        let value = value.into();
+
+        if name.is_empty() || name.contains('=') || name.contains('\0') || value.contains('\0') {
+            return Err(Error::InvalidSetEnv);
+        }
+
+        let req = Request::SetEnv { name, value };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Request that multiple environment variables be set for `cargo test`.
+    pub fn set_env_vars(
+        &self,
+        vars: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Result<()> {
+        let ops = vars.into_iter().map(|(name, value)| EnvOp::Set {
+            name: name.into(),
+            value: value.into(),
+        });
+        self.update_env_vars(ops)
+    }
+
+    /// Request that an environment variable be removed from the environment of `cargo test`.
+    pub fn unset_env_var(&self, name: impl Into<String>) -> Result<()> {
+        self.update_env_vars([EnvOp::Unset { name: name.into() }])
+    }
+
+    /// Request that a path be prepended to a path list environment variable, such as `PATH`, for `cargo test`.
+    ///
+    /// The path is joined with the existing value (if any) using the platform separator, i.e. `:` or `;` on Windows.
+    pub fn prepend_path_var(&self, name: impl Into<String>, path: impl AsRef<Path>) -> Result<()> {
+        let op = EnvOp::PrependPath {
+            name: name.into(),
+            path: path_to_string(path)?,
+        };
+        self.update_env_vars([op])
+    }
+
+    /// Request that a path be appended to a path list environment variable, such as `PATH`, for `cargo test`.
+    ///
+    /// The path is joined with the existing value (if any) using the platform separator, i.e. `:` or `;` on Windows.
+    pub fn append_path_var(&self, name: impl Into<String>, path: impl AsRef<Path>) -> Result<()> {
+        let op = EnvOp::AppendPath {
+            name: name.into(),
+            path: path_to_string(path)?,
+        };
+        self.update_env_vars([op])
+    }
+
+    /// Apply a batch of environment variable operations for `cargo test`, sent to `cargo fixture` in one message.
+    ///
+    /// The operations are applied in order.
+    pub fn update_env_vars(&self, ops: impl IntoIterator<Item = EnvOp>) -> Result<()> {
+        self.update_env_vars_for(EnvTarget::All, ops)
+    }
+
+    /// Like [`update_env_vars()`][FixtureClient::update_env_vars], but only apply the operations
+    /// to the build or the test run of `cargo test`, see [`EnvTarget`].
+    ///
+    /// The environment variables are only ever set for commands run by `cargo fixture`,
+    /// never for the `cargo fixture` process itself.
+    ///
+    /// With an older `cargo fixture` binary, only setting variables for [`EnvTarget::All`] is supported,
+    /// other operations fail with [`Error::RpcUnsupported`].
+    pub fn update_env_vars_for(
+        &self,
+        target: EnvTarget,
+        ops: impl IntoIterator<Item = EnvOp>,
+    ) -> Result<()> {
+        let ops = ops.into_iter().collect::<Vec<_>>();
+        ops.iter().try_for_each(EnvOp::validate)?;
+
+        if !self.socket.has_capability(Capability::UpdateEnv) {
+            // Older hosts only support setting variables one by one
+            for req in set_env_requests(target, ops)? {
+                self.socket.call(req)?.as_ok()?;
+            }
+            return Ok(());
+        }
+
+        let req = Request::UpdateEnv {
+            ops,
+            target,
+            secret: false,
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Like [`set_env_var()`][FixtureClient::set_env_var], but the value is a secret, such as a password.
+    ///
+    /// `cargo fixture` replaces secret values with `***` in its logs, the event log and `cargo fixture status` output.
+    /// With an older `cargo fixture` binary, which would log the value, this fails with [`Error::RpcUnsupported`].
+    pub fn set_secret_env_var(
+        &self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<()> {
+        let op = EnvOp::Set {
+            name: name.into(),
+            value: value.into(),
+        };
+        op.validate()?;
+        self.require_secrets()?;
+
+        let req = Request::UpdateEnv {
+            ops: vec![op],
+            target: EnvTarget::All,
+            secret: true,
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Set additional CLI arguments to be passed to `cargo test`.
+    ///
+    /// No that these are arguments intended for the `cargo test` command itself, to pass arguments to the test binary,
+    /// such as `--nocapture` or similar, use [`set_extra_test_binary_args()`][FixtureClient::set_extra_test_binary_args].
+    pub fn set_extra_cargo_test_args(
+        &self,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<()> {
+        let req = Request::SetExtraTestArgs {
+            args: args.into_iter().map(Into::into).collect(),
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Set additional CLI arguments to be passed to the test binary.
+    ///
+    /// When using CLI, these are usually passed via cargo using the `--` syntax, i.e. `cargo test -- args`...
+    pub fn set_extra_test_binary_args(
+        &self,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<()> {
+        let req = Request::SetExtraHarnessArgs {
+            args: args.into_iter().map(Into::into).collect(),
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Set a value in `cargo fixture`'s in-memory K-V storage.
+    ///
+    /// The value can be any serde-serializable value. After set, it can be retrieved by the test code.
+    ///
+    /// The K-V store internally uses JSON representation.
+    pub fn set_value(&self, key: impl Into<String>, value: impl Serialize) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        let req = Request::SetKeyValue {
+            key: key.into(),
+            value,
+            secret: false,
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Like [`set_value()`][FixtureClient::set_value], but the value is a secret, such as a password.
+    ///
+    /// `cargo fixture` replaces secret values with `***` in its logs, the event log and `cargo fixture status` output.
+    /// With an older `cargo fixture` binary, which would log the value, this fails with [`Error::RpcUnsupported`].
+    pub fn set_secret_value(&self, key: impl Into<String>, value: impl Serialize) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.require_secrets()?;
+
+        let req = Request::SetKeyValue {
+            key: key.into(),
+            value,
+            secret: true,
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Replace the testing program to be executed to a custom one, along with arguments (if any).
+    ///
+    /// This will make `cargo fixture` run the provided program instead of the usual `cargo test` invocation.
+    pub fn set_exec(&self, exec: impl IntoIterator<Item = impl Into<String>>) -> Result<()> {
+        let req = Request::SetExec {
+            exec: exec.into_iter().map(Into::into).collect::<Vec<_>>(),
+        };
+        self.socket.call(req)?.as_ok()
+    }
+
+    /// Signal to `cargo fixture` that the fixture is ready, starting the test run.
+    ///
+    /// This will by default run `cargo test` and return back a `bool` success status,
+    /// once the test run is complete. Note that it may take an arbitrarily long time.
+    ///
+    /// Other calls made concurrently while this is pending fail with [`Error::RpcUnexpectedRequest`].
+    pub fn ready(&self) -> Result<bool> {
+        self.socket.call(Request::Ready)?.as_tests_finished()
+    }
+
+    fn require_secrets(&self) -> Result<()> {
+        if self.socket.has_capability(Capability::Secrets) {
+            Ok(())
+        } else {
+            Err(Error::RpcUnsupported(Capability::Secrets))
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::de::DeserializeOwned;
+
+use crate::{
+    rpc_socket::{blocking::RpcSocket, Request},
+    Result, TestOptions,
+};
+
+/// A blocking RPC client used from test code.
+///
+/// An instance is created using [`TestClient::connect()`],
+/// it's more convenient to use the [`with_fixture`][crate::with_fixture] macro.
+///
+/// All methods take `&self`, the client can be shared between threads (e.g. using an `Arc`)
+/// and requests can be made concurrently.
+pub struct TestClient {
+    socket: RpcSocket,
+}
+
+impl TestClient {
+    /// Connect to running `cargo fixture` process.
+    ///
+    /// The `serial` argument is a way to create a serial test. When set to `true`,
+    /// `cargo fixture` will make sure that no other test client is connected at the same time.
+    /// That is, if any other tests are already running, it will wait for them to finish,
+    /// then let this connection proceed, and only let other connections in once this one is finished.
+    /// Tests are let in in the order they connected, parallel tests connecting after a waiting serial test wait as well.
+    /// The call returns once the test is let in.
+    pub fn connect(serial: bool) -> Result<Self> {
+        Self::connect_with_options(TestOptions::new().serial(serial))
+    }
+
+    /// Connect to running `cargo fixture` process, see [`TestOptions`] for available options.
+    pub fn connect_with_options(options: TestOptions) -> Result<Self> {
+        let socket = RpcSocket::connect(options.hello())?;
+        options.check_supported(|capability| socket.has_capability(capability))?;
+        Ok(Self { socket })
+    }
+
+    /// Get a copy of a value from `cargo fixture`'s in-memory K-V store.
+    ///
+    /// The value expected to have been prepared by the fixture. It can be any serde-serializable value.
+    pub fn get_value<T>(&self, key: impl Into<String>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let req = Request::GetKeyValue { key: key.into() };
+        let value = self.socket.call(req)?.as_value()?;
+        serde_json::from_value(value).map_err(Into::into)
+    }
+}
@@ -1,28 +1,54 @@
+use std::path::Path;
+
 use serde::Serialize;
 
 use crate::{
-    rpc_socket::{ConnectionType, Request, RpcSocket},
+    rpc_socket::{
+        hello, path_to_string, set_env_requests, BoxTransport, Capability, ConnectionType, EnvOp,
+        EnvTarget, Request, RpcSocket, Transport,
+    },
     Error, Result,
 };
 
 /// An RPC client used from fixture code.
 ///
-/// An instance is created using [`FixtureClient::connect()`].
+/// An instance is created using [`FixtureClient::connect()`],
+/// or [`FixtureClient::connect_with()`] when using a custom stream.
+///
+/// All methods take `&self`, the client can be shared between tasks (e.g. using an `Arc`)
+/// and requests can be made concurrently.
 pub struct FixtureClient {
-    socket: RpcSocket,
+    socket: RpcSocket<BoxTransport>,
 }
 
 impl FixtureClient {
     /// Connect to the parent `cargo fixture` process.
+    ///
+    /// Requires one of the async runtime features.
+    #[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
     pub async fn connect() -> Result<Self> {
-        RpcSocket::connect(ConnectionType::Fixture)
+        RpcSocket::connect(hello(ConnectionType::Fixture))
             .await
             .map(|socket| Self { socket })
     }
 
+    /// Connect to the parent `cargo fixture` process using a stream connected by the caller.
+    ///
+    /// The stream can be of any type implementing the `futures-io` traits and needs to be connected
+    /// to the Unix socket at [`socket_path()`][crate::socket_path].
+    /// This is useful with async runtimes not directly supported by this library.
+    pub async fn connect_with(stream: impl Transport + 'static) -> Result<Self> {
+        RpcSocket::connect_with(
+            Box::new(stream) as BoxTransport,
+            hello(ConnectionType::Fixture),
+        )
+        .await
+        .map(|socket| Self { socket })
+    }
+
     /// Request that an environment variable be set for `cargo test`.
     pub async fn set_env_var(
-        &mut self,
+        &self,
         name: impl Into<String>,
         value: impl Into<String>,
     ) -> Result<()> {
@@ -39,15 +65,113 @@ impl FixtureClient {
 
     /// Request that multiple environment variables be set for `cargo test`.
     pub async fn set_env_vars(
-        &mut self,
+        &self,
         vars: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
     ) -> Result<()> {
-        // TODO: batch this when bumping RPC version
-        for (name, value) in vars {
-            self.set_env_var(name, value).await?;
+        let ops = vars.into_iter().map(|(name, value)| EnvOp::Set {
+            name: name.into(),
+            value: value.into(),
+        });
+        self.update_env_vars(ops).await
+    }
+
+    /// Request that an environment variable be removed from the environment of `cargo test`.
+    pub async fn unset_env_var(&self, name: impl Into<String>) -> Result<()> {
+        self.update_env_vars([EnvOp::Unset { name: name.into() }])
+            .await
+    }
+
+    /// Request that a path be prepended to a path list environment variable, such as `PATH`, for `cargo test`.
+    ///
+    /// The path is joined with the existing value (if any) using the platform separator, i.e. `:` or `;` on Windows.
+    pub async fn prepend_path_var(
+        &self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let op = EnvOp::PrependPath {
+            name: name.into(),
+            path: path_to_string(path)?,
+        };
+        self.update_env_vars([op]).await
+    }
+
+    /// Request that a path be appended to a path list environment variable, such as `PATH`, for `cargo test`.
+    ///
+    /// The path is joined with the existing value (if any) using the platform separator, i.e. `:` or `;` on Windows.
+    pub async fn append_path_var(
+        &self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let op = EnvOp::AppendPath {
+            name: name.into(),
+            path: path_to_string(path)?,
+        };
+        self.update_env_vars([op]).await
+    }
+
+    /// Apply a batch of environment variable operations for `cargo test`, sent to `cargo fixture` in one message.
+    ///
+    /// The operations are applied in order.
+    pub async fn update_env_vars(&self, ops: impl IntoIterator<Item = EnvOp>) -> Result<()> {
+        self.update_env_vars_for(EnvTarget::All, ops).await
+    }
+
+    /// Like [`update_env_vars()`][FixtureClient::update_env_vars], but only apply the operations
+    /// to the build or the test run of `cargo test`, see [`EnvTarget`].
+    ///
+    /// The environment variables are only ever set for commands run by `cargo fixture`,
+    /// never for the `cargo fixture` process itself.
+    ///
+    /// With an older `cargo fixture` binary, only setting variables for [`EnvTarget::All`] is supported,
+    /// other operations fail with [`Error::RpcUnsupported`].
+    pub async fn update_env_vars_for(
+        &self,
+        target: EnvTarget,
+        ops: impl IntoIterator<Item = EnvOp>,
+    ) -> Result<()> {
+        let ops = ops.into_iter().collect::<Vec<_>>();
+        ops.iter().try_for_each(EnvOp::validate)?;
+
+        if !self.socket.has_capability(Capability::UpdateEnv) {
+            // Older hosts only support setting variables one by one
+            for req in set_env_requests(target, ops)? {
+                self.socket.call(req).await?.as_ok()?;
+            }
+            return Ok(());
         }
 
-        Ok(())
+        let req = Request::UpdateEnv {
+            ops,
+            target,
+            secret: false,
+        };
+        self.socket.call(req).await?.as_ok()
+    }
+
+    /// Like [`set_env_var()`][FixtureClient::set_env_var], but the value is a secret, such as a password.
+    ///
+    /// `cargo fixture` replaces secret values with `***` in its logs, the event log and `cargo fixture status` output.
+    /// With an older `cargo fixture` binary, which would log the value, this fails with [`Error::RpcUnsupported`].
+    pub async fn set_secret_env_var(
+        &self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<()> {
+        let op = EnvOp::Set {
+            name: name.into(),
+            value: value.into(),
+        };
+        op.validate()?;
+        self.require_secrets()?;
+
+        let req = Request::UpdateEnv {
+            ops: vec![op],
+            target: EnvTarget::All,
+            secret: true,
+        };
+        self.socket.call(req).await?.as_ok()
     }
 
     /// Set additional CLI arguments to be passed to `cargo test`.
@@ -55,7 +179,7 @@ impl FixtureClient {
     /// No that these are arguments intended for the `cargo test` command itself, to pass arguments to the test binary,
     /// such as `--nocapture` or similar, use [`set_extra_test_binary_args()`][FixtureClient::set_extra_test_binary_args].
     pub async fn set_extra_cargo_test_args(
-        &mut self,
+        &self,
         args: impl IntoIterator<Item = impl Into<String>>,
     ) -> Result<()> {
         let req = Request::SetExtraTestArgs {
@@ -68,7 +192,7 @@ impl FixtureClient {
     ///
     /// When using CLI, these are usually passed via cargo using the `--` syntax, i.e. `cargo test -- args`...
     pub async fn set_extra_test_binary_args(
-        &mut self,
+        &self,
         args: impl IntoIterator<Item = impl Into<String>>,
     ) -> Result<()> {
         let req = Request::SetExtraHarnessArgs {
@@ -82,11 +206,32 @@ impl FixtureClient {
     /// The value can be any serde-serializable value. After set, it can be retrieved by the test code.
     ///
     /// The K-V store internally uses JSON representation.
-    pub async fn set_value(&mut self, key: impl Into<String>, value: impl Serialize) -> Result<()> {
+    pub async fn set_value(&self, key: impl Into<String>, value: impl Serialize) -> Result<()> {
         let value = serde_json::to_value(value)?;
         let req = Request::SetKeyValue {
             key: key.into(),
             value,
+            secret: false,
+        };
+        self.socket.call(req).await?.as_ok()
+    }
+
+    /// Like [`set_value()`][FixtureClient::set_value], but the value is a secret, such as a password.
+    ///
+    /// `cargo fixture` replaces secret values with `***` in its logs, the event log and `cargo fixture status` output.
+    /// With an older `cargo fixture` binary, which would log the value, this fails with [`Error::RpcUnsupported`].
+    pub async fn set_secret_value(
+        &self,
+        key: impl Into<String>,
+        value: impl Serialize,
+    ) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.require_secrets()?;
+
+        let req = Request::SetKeyValue {
+            key: key.into(),
+            value,
+            secret: true,
         };
         self.socket.call(req).await?.as_ok()
     }
@@ -94,10 +239,7 @@ impl FixtureClient {
     /// Replace the testing program to be executed to a custom one, along with arguments (if any).
     ///
     /// This will make `cargo fixture` run the provided program instead of the usual `cargo test` invocation.
-    pub async fn set_exec(
-        &mut self,
-        exec: impl IntoIterator<Item = impl Into<String>>,
-    ) -> Result<()> {
+    pub async fn set_exec(&self, exec: impl IntoIterator<Item = impl Into<String>>) -> Result<()> {
         let req = Request::SetExec {
             exec: exec.into_iter().map(Into::into).collect::<Vec<_>>(),
         };
@@ -108,7 +250,17 @@ impl FixtureClient {
     ///
     /// This will by default run `cargo test` and return back a `bool` success status,
     /// once the test run is complete. Note that it may take an arbitrarily long time.
-    pub async fn ready(&mut self) -> Result<bool> {
+    ///
+    /// Other calls made concurrently while this is pending fail with [`Error::RpcUnexpectedRequest`].
+    pub async fn ready(&self) -> Result<bool> {
         self.socket.call(Request::Ready).await?.as_tests_finished()
     }
+
+    fn require_secrets(&self) -> Result<()> {
+        if self.socket.has_capability(Capability::Secrets) {
+            Ok(())
+        } else {
+            Err(Error::RpcUnsupported(Capability::Secrets))
+        }
+    }
 }
@@ -1,16 +1,19 @@
 use serde::de::DeserializeOwned;
 
 use crate::{
-    rpc_socket::{ConnectionType, Request, RpcSocket},
-    Result,
+    rpc_socket::{BoxTransport, Request, RpcSocket, Transport},
+    Result, TestOptions,
 };
 
 /// An RPC client used from test code.
 ///
-/// An instance is created using [`TestClient::connect()`],
+/// An instance is created using [`TestClient::connect()`] or [`TestClient::connect_with()`],
 /// it's more convenient to use the [`with_fixture`][crate::with_fixture] macro.
+///
+/// All methods take `&self`, the client can be shared between tasks (e.g. using an `Arc`)
+/// and requests can be made concurrently.
 pub struct TestClient {
-    socket: RpcSocket,
+    socket: RpcSocket<BoxTransport>,
 }
 
 impl TestClient {
@@ -20,8 +23,30 @@ impl TestClient {
     /// `cargo fixture` will make sure that no other test client is connected at the same time.
     /// That is, if any other tests are already running, it will wait for them to finish,
     /// then let this connection proceed, and only let other connections in once this one is finished.
+    /// Tests are let in in the order they connected, parallel tests connecting after a waiting serial test wait as well.
+    /// The call returns once the test is let in.
+    ///
+    /// Requires one of the async runtime features.
+    #[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
     pub async fn connect(serial: bool) -> Result<Self> {
-        RpcSocket::connect(ConnectionType::client(serial))
+        Self::connect_with_options(TestOptions::new().serial(serial)).await
+    }
+
+    /// Connect to running `cargo fixture` process, see [`TestOptions`] for available options.
+    ///
+    /// Requires one of the async runtime features.
+    #[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
+    pub async fn connect_with_options(options: TestOptions) -> Result<Self> {
+        let socket = RpcSocket::connect(options.hello()).await?;
+        options.check_supported(|capability| socket.has_capability(capability))?;
+        Ok(Self { socket })
+    }
+
+    /// Connect to running `cargo fixture` process using a stream connected by the caller,
+    /// see [`FixtureClient::connect_with()`][crate::FixtureClient::connect_with].
+    pub async fn connect_with(stream: impl Transport + 'static, serial: bool) -> Result<Self> {
+        let stream = Box::new(stream) as BoxTransport;
+        RpcSocket::connect_with(stream, TestOptions::new().serial(serial).hello())
             .await
             .map(|socket| Self { socket })
     }
@@ -29,7 +54,7 @@ impl TestClient {
     /// Get a copy of a value from `cargo fixture`'s in-memory K-V store.
     ///
     /// The value expected to have been prepared by the fixture. It can be any serde-serializable value.
-    pub async fn get_value<T>(&mut self, key: impl Into<String>) -> Result<T>
+    pub async fn get_value<T>(&self, key: impl Into<String>) -> Result<T>
     where
         T: DeserializeOwned,
     {
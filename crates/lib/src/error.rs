@@ -27,6 +27,22 @@ pub enum Error {
     #[error("Unexpected RPC response: {0:?}")]
     RpcMismatch(crate::rpc_socket::Response),
 
+    /// `cargo fixture` rejected a request not allowed on this connection.
+    #[error("Request rejected by cargo fixture: {0}")]
+    RpcUnexpectedRequest(String),
+
+    /// `cargo fixture` is not compatible with this library version.
+    #[error("Incompatible cargo fixture version: {0}")]
+    RpcIncompatibleVersion(String),
+
+    /// The `cargo fixture` binary is too old to support a feature of this library.
+    #[error("The cargo fixture binary doesn't support `{}`, it needs to be upgraded", .0.as_ref())]
+    RpcUnsupported(crate::rpc_socket::Capability),
+
+    /// Other error reported by `cargo fixture`.
+    #[error("cargo fixture error: {0}")]
+    RpcHost(String),
+
     /// Connection interrupted prematurely.
     #[error("cargo fixture socket unexpectedly hung up")]
     RpcHangup,
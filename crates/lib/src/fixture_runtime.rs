@@ -0,0 +1,152 @@
+//! Support code for the [`fixture_main`][crate::fixture_main] macro.
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    process::ExitCode,
+};
+
+use futures_util::FutureExt as _;
+
+use crate::FixtureClient;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+type CleanupFn = Box<dyn FnOnce(bool) -> Pin<Box<dyn Future<Output = Result<(), BoxError>>>>>;
+
+/// Cleanup code returned from a [`fixture_main`][crate::fixture_main] function.
+///
+/// The cleanup is run once the tests are finished, it's passed the test run success status.
+/// It's also run if the test run fails with an error or a panic.
+#[must_use = "the cleanup has to be returned from the fixture_main function"]
+pub struct Cleanup {
+    inner: Option<CleanupFn>,
+}
+
+impl Cleanup {
+    /// Run the provided async closure as cleanup.
+    pub fn new<F, Fut, E>(cleanup: F) -> Self
+    where
+        F: FnOnce(bool) -> Fut + 'static,
+        Fut: Future<Output = Result<(), E>> + 'static,
+        E: Into<BoxError>,
+    {
+        let cleanup: CleanupFn = Box::new(move |success| {
+            Box::pin(async move { cleanup(success).await.map_err(Into::into) })
+        });
+        Self {
+            inner: Some(cleanup),
+        }
+    }
+
+    /// Drop the provided value as cleanup, useful for RAII guards.
+    pub fn drop_value<T: 'static>(value: T) -> Self {
+        Self::new(move |_| async move {
+            drop(value);
+            Ok::<_, BoxError>(())
+        })
+    }
+
+    /// No cleanup.
+    pub fn none() -> Self {
+        Self { inner: None }
+    }
+
+    async fn run(self, success: bool) -> Result<(), BoxError> {
+        match self.inner {
+            Some(cleanup) => cleanup(success).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for Cleanup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cleanup")
+            .field("is_some", &self.inner.is_some())
+            .finish()
+    }
+}
+
+/// Run a future to completion on the async runtime selected by a feature.
+#[cfg(feature = "smol")]
+pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+    smol::block_on(future)
+}
+
+/// Run a future to completion on the async runtime selected by a feature.
+#[cfg(feature = "tokio")]
+pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Could not create Tokio runtime")
+        .block_on(future)
+}
+
+/// Run a future to completion on the async runtime selected by a feature.
+#[cfg(feature = "async-std")]
+pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+    async_std::task::block_on(future)
+}
+
+/// Connect to `cargo fixture`, panics on failure.
+pub async fn connect() -> FixtureClient {
+    FixtureClient::connect()
+        .await
+        .expect("Could not connect to cargo fixture")
+}
+
+/// Run the tests and cleanup once setup is done, report errors.
+pub async fn run<E>(fixture: FixtureClient, setup: Result<Cleanup, E>) -> ExitCode
+where
+    E: fmt::Debug,
+{
+    let cleanup = match setup {
+        Ok(cleanup) => cleanup,
+        Err(err) => {
+            eprintln!("Error: fixture setup failed: {err:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Make sure the cleanup runs whatever happens with the test run
+    let tests = AssertUnwindSafe(fixture.ready()).catch_unwind().await;
+    let success = matches!(tests, Ok(Ok(true)));
+    let cleanup = cleanup.run(success).await;
+
+    let mut exit_code = ExitCode::SUCCESS;
+    match tests {
+        Ok(Ok(_)) => {}
+        Ok(Err(err)) => {
+            eprintln!("Error: test run failed: {err:?}");
+            exit_code = ExitCode::FAILURE;
+        }
+        Err(panic) => {
+            report_cleanup(cleanup);
+            panic::resume_unwind(panic);
+        }
+    }
+    if !report_cleanup(cleanup) {
+        exit_code = ExitCode::FAILURE;
+    }
+
+    exit_code
+}
+
+fn report_cleanup(cleanup: Result<(), BoxError>) -> bool {
+    match cleanup {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Error: fixture cleanup failed: {err}");
+            let mut source = err.source();
+            while let Some(err) = source {
+                eprintln!("  caused by: {err}");
+                source = err.source();
+            }
+            false
+        }
+    }
+}
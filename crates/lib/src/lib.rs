@@ -6,22 +6,54 @@
 //! - [`FixtureClient`] &ndash; to be used from fixture code
 //! - [`TestClient`] &ndash; to be used from test code
 //!
-//! The [`with_fixture`] macros is provided as well for easy fixture tests definition.
+//! The [`with_fixture`] macros is provided as well for easy fixture tests definition,
+//! and the [`fixture_main`] macro for fixture programs.
 //!
 //! ## Features
 //! The library supports the following async runtimes, selectable with a feature of the same name:
 //! - [`tokio`](https://tokio.rs/)
 //! - [`smol`](https://docs.rs/smol)
+//! - [`async-std`](https://docs.rs/async-std) (not supported on Windows)
 //!
-//! You have to activate exactly one of these features to use the library.
+//! You have to activate at most one of these features to use the async clients.
+//!
+//! With other runtimes, activate the `futures-io` feature instead and connect the clients using
+//! [`FixtureClient::connect_with()`] and [`TestClient::connect_with()`] with a stream implementing
+//! the [`futures-io`](https://docs.rs/futures-io) traits.
+//!
+//! Alternatively, the `blocking` feature provides clients for synchronous code in the [`blocking`] module,
+//! which don't need any async runtime. It can be combined with either of the runtime features.
+
+#[cfg(any(
+    all(feature = "smol", feature = "tokio"),
+    all(feature = "smol", feature = "async-std"),
+    all(feature = "tokio", feature = "async-std"),
+))]
+compile_error!("at most one of the smol, tokio, async-std features can be enabled");
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "futures-io")]
 mod client_fixture;
+#[cfg(feature = "futures-io")]
 mod client_test;
 pub mod error;
+#[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
+#[doc(hidden)]
+pub mod fixture_runtime;
 #[doc(hidden)]
 pub mod rpc_socket;
+mod test_options;
 
-pub use cargo_fixture_macros::with_fixture;
+pub use cargo_fixture_macros::{fixture_main, with_fixture};
+#[cfg(feature = "futures-io")]
 pub use client_fixture::FixtureClient;
+#[cfg(feature = "futures-io")]
 pub use client_test::TestClient;
 pub use error::{Error, Result};
+#[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
+pub use fixture_runtime::Cleanup;
+pub use rpc_socket::{socket_path, Capability, EnvOp, EnvTarget};
+#[doc(hidden)]
+pub use test_options::parse_duration_ms;
+pub use test_options::TestOptions;
@@ -1,14 +1,65 @@
-//! FIXME: doc-comment
+//! The RPC protocol spoken between `cargo fixture` and its clients.
+//!
+//! Messages are JSON objects, one per line. A connection starts with a `Hello` handshake,
+//! in which the client sends the range of protocol revisions it supports along with a list of capabilities.
+//! The host picks the highest revision supported by both sides and replies with its own capabilities.
+//! The package versions of the host and the client don't need to match.
+//!
+//! Protocol revisions:
+//!
+//! - Protocol 1: strictly one request followed by one response.
+//! - Protocol 2: each request carries an `id` which is repeated in the corresponding response,
+//!   responses may arrive out of order and requests may be pipelined.
+//!   Messages from the host without an `id` are notifications.
+//!
+//! The `Hello` message itself and its response are always sent without an `id`.
+//!
+//! The host answers requests it can't handle with an `Error` response. If the handshake fails,
+//! the `Error` response is sent in place of the `Hello` response and the connection is closed.
+//!
+//! Values sent with the `secret` flag are replaced with [`REDACTED`] whenever messages are logged or reported.
 
-use std::{env, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
 
 use log::trace;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
+use strum::AsRefStr;
 
 use crate::{Error, Result};
 
+/// The highest protocol revision supported by this library.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// The lowest protocol revision supported by this library.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Capabilities supported by this library.
+pub const CAPABILITIES: &[Capability] = &[
+    Capability::UpdateEnv,
+    Capability::ErrorResponse,
+    Capability::Secrets,
+    Capability::TestTimeout,
+];
+
+/// Replacement of secret values in logs and reports.
+pub const REDACTED: &str = "***";
+
+#[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
 pub mod platform;
-use platform::*;
+
+#[cfg(feature = "futures-io")]
+mod async_socket;
+#[cfg(any(feature = "futures-io", feature = "blocking"))]
+mod calls;
+#[cfg(feature = "futures-io")]
+pub use async_socket::{BoxTransport, RpcSocket, Transport};
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
 #[serde(rename_all = "kebab-case")]
@@ -16,6 +67,8 @@ pub enum ConnectionType {
     Fixture,
     Client,
     ClientSerial,
+    /// Read-only session inspection, used by `cargo fixture status`.
+    Inspector,
 }
 
 impl ConnectionType {
@@ -28,22 +81,145 @@ impl ConnectionType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Optional features of the protocol, advertised by both sides in the handshake.
+///
+/// A library feature that needs a capability the host lacks fails with [`Error::RpcUnsupported`].
+#[derive(Serialize, Deserialize, AsRefStr, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Capability {
+    /// The `UpdateEnv` request, i.e. batched env var updates, unsetting, path lists and env targets.
+    UpdateEnv,
+    /// `Error` responses.
+    ErrorResponse,
+    /// The `secret` flag of `UpdateEnv` and `SetKeyValue`, the host redacts such values.
+    Secrets,
+    /// The `timeout_ms` field of a test connection's `Hello`, the host enforces the time limit.
+    TestTimeout,
+    /// A capability not known to this version.
+    #[serde(other)]
+    Unknown,
+}
+
+/// An operation on an environment variable, see [`FixtureClient::update_env_vars()`][crate::FixtureClient::update_env_vars].
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum EnvOp {
+    /// Set the variable, overwriting any previous value.
+    Set {
+        /// Variable name.
+        name: String,
+        /// Variable value.
+        value: String,
+    },
+    /// Remove the variable.
+    Unset {
+        /// Variable name.
+        name: String,
+    },
+    /// Prepend a path to a path list variable, such as `PATH`, using the platform separator.
+    PrependPath {
+        /// Variable name.
+        name: String,
+        /// The path to prepend.
+        path: String,
+    },
+    /// Append a path to a path list variable, such as `PATH`, using the platform separator.
+    AppendPath {
+        /// Variable name.
+        name: String,
+        /// The path to append.
+        path: String,
+    },
+}
+
+impl EnvOp {
+    /// Name of the variable this operation applies to.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Set { name, .. }
+            | Self::Unset { name }
+            | Self::PrependPath { name, .. }
+            | Self::AppendPath { name, .. } => name,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        let name = self.name();
+        if name.is_empty() || name.contains('=') || name.contains('\0') {
+            return Err(Error::InvalidSetEnv);
+        }
+
+        match self {
+            Self::Set { value, .. } if value.contains('\0') => Err(Error::InvalidSetEnv),
+            Self::PrependPath { path, .. } | Self::AppendPath { path, .. }
+                if path.contains('\0') || env::join_paths([path]).is_err() =>
+            {
+                Err(Error::InvalidSetEnv)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which commands run by `cargo fixture` an environment variable update applies to.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvTarget {
+    /// Both the build and the test run, this is the default.
+    #[default]
+    All,
+    /// Only the test run, i.e. test binaries, not the compilation of tests.
+    Test,
+    /// Only the compilation of tests, i.e. `rustc`, build scripts and proc macros.
+    ///
+    /// To achieve this, `cargo fixture` builds tests using `cargo test --no-run` first and then runs them in a second step.
+    /// Note that crates which track the variable (using `env!()` or `cargo:rerun-if-env-changed`)
+    /// are rebuilt without it in the second step.
+    Build,
+}
+
+#[derive(Serialize, Deserialize, AsRefStr, Debug)]
 #[serde(tag = "msg", content = "data")]
 pub enum Request {
     Hello {
         version: u32,
         connection_type: ConnectionType,
+        /// Highest protocol revision supported by the client, missing in protocol 1 clients.
+        #[serde(default = "protocol_v1")]
+        protocol: u32,
+        /// Lowest protocol revision supported by the client.
+        #[serde(default = "protocol_v1")]
+        min_protocol: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+        /// Name of the test, test connections only.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        /// Time limit of a test connection in milliseconds, enforced by the host,
+        /// requires [`Capability::TestTimeout`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_ms: Option<u64>,
     },
+    /// Kept for compatibility with older library versions, superseded by `UpdateEnv`.
     SetEnv {
-        // TODO: support make this an array when bumping RPC version
-        // cf. FixtureClient::set_env_vars()
         name: String,
         value: String,
     },
+    UpdateEnv {
+        ops: Vec<EnvOp>,
+        #[serde(default)]
+        target: EnvTarget,
+        /// The values are secret, requires [`Capability::Secrets`].
+        #[serde(default, skip_serializing_if = "is_false")]
+        secret: bool,
+    },
     SetKeyValue {
         key: String,
         value: serde_json::Value,
+        /// The value is secret, requires [`Capability::Secrets`].
+        #[serde(default, skip_serializing_if = "is_false")]
+        secret: bool,
     },
     GetKeyValue {
         key: String,
@@ -58,119 +234,403 @@ pub enum Request {
         exec: Vec<String>,
     },
     Ready,
+    /// Inspector connections only.
+    GetStatus,
+}
+
+fn protocol_v1() -> u32 {
+    1
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "msg", content = "data")]
 pub enum Response {
     Ok,
+    /// Handshake response to protocol 2+ clients, carries the negotiated protocol revision
+    /// and the host's capabilities.
+    Hello {
+        protocol: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
     TestsFinished {
         success: bool,
     },
     KeyValue {
         key: String,
         value: Option<serde_json::Value>,
+        /// The value was set as a secret, so that clients don't log it either.
+        #[serde(default, skip_serializing_if = "is_false")]
+        secret: bool,
     },
+    Status(Box<SessionStatus>),
+    /// The host rejected a request, or the connection as a whole in case of the handshake.
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+/// Reason of an error response.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The request is not allowed on this connection.
+    UnexpectedRequest,
+    /// The host and the library versions are not compatible.
+    IncompatibleVersion,
+    /// Any other error, also used for codes not known to this library version.
+    #[serde(other)]
+    Other,
 }
 
 impl Response {
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Error {
+            code,
+            message: message.into(),
+        }
+    }
+
     pub fn as_ok(self) -> Result<()> {
         match self {
             Self::Ok => Ok(()),
-            _ => Error::RpcMismatch(self).into(),
+            _ => self.into_error().into(),
         }
     }
 
     pub fn as_tests_finished(self) -> Result<bool> {
         match self {
             Response::TestsFinished { success } => Ok(success),
-            _ => Error::RpcMismatch(self).into(),
+            _ => self.into_error().into(),
         }
     }
 
     pub fn as_value(self) -> Result<serde_json::Value> {
         match self {
-            Response::KeyValue { key, value } => value.ok_or(Error::MissingKeyValue(key)),
-            _ => Error::RpcMismatch(self).into(),
+            Response::KeyValue { key, value, .. } => value.ok_or(Error::MissingKeyValue(key)),
+            _ => self.into_error().into(),
+        }
+    }
+
+    /// Convert an unexpected response to an error, surfacing the reason if the host sent an error response.
+    pub fn into_error(self) -> Error {
+        match self {
+            Self::Error { code, message } => match code {
+                ErrorCode::UnexpectedRequest => Error::RpcUnexpectedRequest(message),
+                ErrorCode::IncompatibleVersion => Error::RpcIncompatibleVersion(message),
+                ErrorCode::Other => Error::RpcHost(message),
+            },
+            other => Error::RpcMismatch(other),
         }
     }
 }
 
-// TODO: use interior mutability? so that all uses don't have to be mut
-#[derive(Debug)]
-pub struct RpcSocket {
-    socket: BufReader<UnixStream>,
-    buffer: String,
+/// A message along with its ID, used to match responses to requests in protocol 2.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Envelope<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub msg: T,
 }
 
-impl RpcSocket {
-    pub(crate) async fn connect(connection_type: ConnectionType) -> Result<Self> {
-        let path = PathBuf::from(env::var_os("CARGO_FIXTURE_SOCKET").ok_or(Error::RpcNoEnvVar)?);
-        let stream = UnixStream::connect(path).await.map_err(Error::RpcIo)?;
-        let mut this = Self::new(stream);
+/// Path of the `cargo fixture` socket, as passed in the `CARGO_FIXTURE_SOCKET` environment variable.
+///
+/// This is useful for connecting a custom stream, see [`FixtureClient::connect_with()`][crate::FixtureClient::connect_with].
+pub fn socket_path() -> Result<PathBuf> {
+    env::var_os("CARGO_FIXTURE_SOCKET")
+        .map(PathBuf::from)
+        .ok_or(Error::RpcNoEnvVar)
+}
 
-        // Perform handshake
-        let version = env!("CARGO_PKG_VERSION_MAJOR").parse::<u32>().unwrap();
-        this.call(Request::Hello {
-            version,
-            connection_type,
-        })
-        .await?
-        .as_ok()?;
-        Ok(this)
-    }
-
-    pub fn new(stream: UnixStream) -> Self {
-        let socket = BufReader::new(stream);
-        let buffer = String::with_capacity(1024);
-        Self { socket, buffer }
-    }
-
-    pub async fn send<T>(&mut self, msg: T) -> Result<()>
-    where
-        T: Serialize,
-    {
-        let mut msg = serde_json::to_string(&msg).map_err(Error::RpcSerde)?;
-        trace!("RPC send: {msg}");
-        msg.push('\n');
-        self.socket
-            .get_mut()
-            .write_all(msg.as_bytes())
-            .await
-            .map_err(Error::RpcIo)?;
-        Ok(())
-    }
-
-    pub async fn recv<T>(&mut self) -> Result<Option<T>>
-    where
-        T: DeserializeOwned,
-    {
-        self.buffer.clear();
-        let num_read = self
-            .socket
-            .read_line(&mut self.buffer)
-            .await
-            .map_err(Error::RpcIo)?;
-        if num_read == 0 {
-            Ok(None)
-        } else {
-            let msg = &self.buffer.trim();
-            trace!("RPC recv: {msg}");
-            serde_json::from_str(msg).map(Some).map_err(Error::RpcSerde)
+/// State of a `cargo fixture` session, as reported to inspector connections.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct SessionStatus {
+    /// PID of the `cargo fixture` process.
+    pub pid: u32,
+    pub fixture: String,
+    pub env: Vec<EnvVarStatus>,
+    pub kv: BTreeMap<String, serde_json::Value>,
+    /// Tests currently connected.
+    pub tests: Vec<TestStatus>,
+    /// Tests waiting to be let in, in order.
+    pub queue: Vec<TestStatus>,
+    pub phases: Vec<PhaseStatus>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EnvVarStatus {
+    pub name: String,
+    /// `None` if the variable is unset.
+    pub value: Option<String>,
+    pub target: EnvTarget,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TestStatus {
+    pub conn: u64,
+    pub name: Option<String>,
+    pub serial: bool,
+    /// For how long the test has been connected or waiting.
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PhaseStatus {
+    pub phase: String,
+    pub duration_ms: u64,
+}
+
+/// The client side handshake message.
+pub fn hello(connection_type: ConnectionType) -> Request {
+    Request::Hello {
+        version: env!("CARGO_PKG_VERSION_MAJOR").parse::<u32>().unwrap(),
+        connection_type,
+        protocol: PROTOCOL_VERSION,
+        min_protocol: MIN_PROTOCOL_VERSION,
+        capabilities: CAPABILITIES.to_vec(),
+        name: None,
+        timeout_ms: None,
+    }
+}
+
+/// Evaluate the host's response to [`hello()`], returns the negotiated protocol and the host's capabilities.
+fn handshake_result(resp: Response) -> Result<(u32, Vec<Capability>)> {
+    let res = match resp {
+        // Host only supports protocol 1
+        Response::Ok => (1, vec![]),
+        Response::Hello {
+            protocol,
+            capabilities,
+        } if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&protocol) => {
+            (protocol, capabilities)
+        }
+        other => return other.into_error().into(),
+    };
+    trace!("RPC protocol: {}, host capabilities: {:?}", res.0, res.1);
+    Ok(res)
+}
+
+/// Replace secret values in a serialized message with [`REDACTED`], for logging.
+pub fn redact(msg: &str) -> Cow<'_, str> {
+    if !msg.contains(r#""secret":true"#) {
+        return Cow::Borrowed(msg);
+    }
+
+    match serde_json::from_str(msg) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            Cow::Owned(value.to_string())
         }
+        // Can't tell which part is secret
+        Err(_) => Cow::Borrowed(REDACTED),
     }
+}
+
+/// Replace secret values in a message represented as JSON with [`REDACTED`], for logging.
+///
+/// Looks for objects with `"secret": true`, i.e. the data of `UpdateEnv`, `SetKeyValue` and `KeyValue` messages.
+pub fn redact_value(value: &mut serde_json::Value) {
+    use serde_json::Value;
 
-    pub(crate) async fn call(&mut self, request: Request) -> Result<Response> {
-        self.send(request).await?;
-        self.recv().await?.ok_or(Error::RpcHangup)
+    match value {
+        Value::Object(obj) => {
+            if obj.get("secret") == Some(&Value::Bool(true)) {
+                if let Some(value) = obj.get_mut("value") {
+                    *value = REDACTED.into();
+                }
+                let ops = obj.get_mut("ops").and_then(Value::as_array_mut);
+                for op in ops.into_iter().flatten() {
+                    for field in ["value", "path"] {
+                        if let Some(value) = op.get_mut(field) {
+                            *value = REDACTED.into();
+                        }
+                    }
+                }
+            }
+            obj.values_mut().for_each(redact_value);
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_value),
+        _ => {}
     }
 }
 
+/// Turn env var operations into `SetEnv` requests, for hosts without the `UpdateEnv` request.
+pub(crate) fn set_env_requests(target: EnvTarget, ops: Vec<EnvOp>) -> Result<Vec<Request>> {
+    if target != EnvTarget::All {
+        return Err(Error::RpcUnsupported(Capability::UpdateEnv));
+    }
+
+    ops.into_iter()
+        .map(|op| match op {
+            EnvOp::Set { name, value } => Ok(Request::SetEnv { name, value }),
+            _ => Err(Error::RpcUnsupported(Capability::UpdateEnv)),
+        })
+        .collect()
+}
+
+pub(crate) fn path_to_string(path: impl AsRef<Path>) -> Result<String> {
+    path.as_ref()
+        .to_str()
+        .map(ToOwned::to_owned)
+        .ok_or(Error::InvalidSetEnv)
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
 
-    use super::Request;
+    use super::{
+        redact, Capability, EnvOp, EnvTarget, Envelope, ErrorCode, Request, Response, REDACTED,
+    };
+    use crate::Error;
+
+    #[test]
+    fn env_op_serde() {
+        let msg = Request::UpdateEnv {
+            target: EnvTarget::Test,
+            ops: vec![
+                EnvOp::Set {
+                    name: "FOO".to_string(),
+                    value: "bar".to_string(),
+                },
+                EnvOp::Unset {
+                    name: "BAZ".to_string(),
+                },
+                EnvOp::PrependPath {
+                    name: "PATH".to_string(),
+                    path: "/opt/bin".to_string(),
+                },
+            ],
+            secret: false,
+        };
+        let msg = serde_json::to_value(&msg).unwrap();
+
+        let expected = json!({
+            "msg": "UpdateEnv",
+            "data": {"ops": [
+                {"op": "set", "name": "FOO", "value": "bar"},
+                {"op": "unset", "name": "BAZ"},
+                {"op": "prepend-path", "name": "PATH", "path": "/opt/bin"},
+            ], "target": "test"}
+        });
+        assert_eq!(msg, expected);
+    }
+
+    #[test]
+    fn envelope_serde() {
+        let msg = Envelope {
+            id: Some(3),
+            msg: Request::GetKeyValue {
+                key: "foo".to_string(),
+            },
+        };
+        let msg = serde_json::to_value(&msg).unwrap();
+        let expected = json!({
+            "id": 3,
+            "msg": "GetKeyValue",
+            "data": {"key": "foo"}
+        });
+        assert_eq!(msg, expected);
+
+        // Protocol 1 messages have no ID
+        let msg: Envelope<Request> = serde_json::from_str(r#"{"msg": "Ready"}"#).unwrap();
+        assert!(msg.id.is_none());
+        assert!(matches!(msg.msg, Request::Ready));
+
+        // Protocol 1 hello
+        let msg: Request = serde_json::from_value(json!({
+            "msg": "Hello",
+            "data": {"version": 1, "connection_type": "client"}
+        }))
+        .unwrap();
+        assert!(matches!(
+            msg,
+            Request::Hello {
+                protocol: 1,
+                min_protocol: 1,
+                ref capabilities,
+                ..
+            } if capabilities.is_empty()
+        ));
+    }
+
+    #[test]
+    fn capabilities_serde() {
+        let msg: Response = serde_json::from_value(json!({
+            "msg": "Hello",
+            "data": {"protocol": 2, "capabilities": ["update-env", "something-new"]}
+        }))
+        .unwrap();
+        let Response::Hello { capabilities, .. } = msg else {
+            panic!("unexpected response: {msg:?}");
+        };
+        assert_eq!(capabilities, [Capability::UpdateEnv, Capability::Unknown]);
+        assert_eq!(Capability::ErrorResponse.as_ref(), "error-response");
+    }
+
+    #[test]
+    fn error_response_serde() {
+        let msg = Response::error(ErrorCode::UnexpectedRequest, "nope");
+        let msg = serde_json::to_value(&msg).unwrap();
+        let expected = json!({
+            "msg": "Error",
+            "data": {"code": "unexpected-request", "message": "nope"}
+        });
+        assert_eq!(msg, expected);
+
+        // Codes unknown to this version
+        let msg: Response = serde_json::from_value(json!({
+            "msg": "Error",
+            "data": {"code": "something-new", "message": "nope"}
+        }))
+        .unwrap();
+        assert!(matches!(msg.into_error(), Error::RpcHost(message) if message == "nope"));
+    }
+
+    #[test]
+    fn secret_redaction() {
+        let msg = Envelope {
+            id: Some(1),
+            msg: Request::UpdateEnv {
+                target: EnvTarget::All,
+                ops: vec![EnvOp::Set {
+                    name: "PASSWORD".to_string(),
+                    value: "hunter2".to_string(),
+                }],
+                secret: true,
+            },
+        };
+        let msg = serde_json::to_string(&msg).unwrap();
+        let redacted = redact(&msg);
+        assert!(!redacted.contains("hunter2"), "{redacted}");
+        assert!(redacted.contains("PASSWORD"), "{redacted}");
+        assert!(redacted.contains(REDACTED), "{redacted}");
+
+        let msg = Response::KeyValue {
+            key: "password".to_string(),
+            value: Some(json!({"nested": "hunter2"})),
+            secret: true,
+        };
+        let msg = serde_json::to_string(&msg).unwrap();
+        let redacted = redact(&msg);
+        assert!(!redacted.contains("hunter2"), "{redacted}");
+
+        // Messages without secrets are left alone, and the flag isn't sent at all
+        let msg = Request::SetKeyValue {
+            key: "password".to_string(),
+            value: json!("plain"),
+            secret: false,
+        };
+        let msg = serde_json::to_string(&msg).unwrap();
+        assert!(!msg.contains("\"secret\""), "{msg}");
+        assert_eq!(redact(&msg), msg);
+    }
 
     // TODO: the same for response
     #[test]
@@ -0,0 +1,194 @@
+//! The async socket, used by the async clients as well as the `cargo fixture` host.
+//!
+//! The socket is generic over the transport stream, which can be anything implementing the `futures-io` traits.
+
+use futures_util::{
+    future::{self, Either},
+    io::{
+        AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf,
+        WriteHalf,
+    },
+    lock::Mutex as AsyncMutex,
+    pin_mut,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    calls::{decode, encode, Calls},
+    handshake_result, Capability, Envelope, Request, Response,
+};
+use crate::{Error, Result};
+
+/// A stream an [`RpcSocket`] can communicate over.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// A type-erased transport, used by the clients so that they don't need to be generic.
+pub type BoxTransport = Box<dyn Transport>;
+
+#[derive(Debug)]
+struct Reader<S> {
+    socket: BufReader<ReadHalf<S>>,
+    buffer: String,
+}
+
+impl<S> Reader<S>
+where
+    S: Transport,
+{
+    async fn recv<T>(&mut self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.buffer.clear();
+        let num_read = self
+            .socket
+            .read_line(&mut self.buffer)
+            .await
+            .map_err(Error::RpcIo)?;
+        decode(num_read, &self.buffer)
+    }
+}
+
+/// A connection endpoint, used both by clients and the `cargo fixture` host.
+///
+/// All methods take `&self`, concurrent calls are supported. Responses read from the socket
+/// by one caller on behalf of another are handed over to the other caller.
+#[derive(Debug)]
+pub struct RpcSocket<S> {
+    reader: AsyncMutex<Reader<S>>,
+    writer: AsyncMutex<WriteHalf<S>>,
+    protocol: u32,
+    /// Capabilities of the other side.
+    capabilities: Vec<Capability>,
+    calls: Calls,
+}
+
+#[cfg(any(feature = "smol", feature = "tokio", feature = "async-std"))]
+impl RpcSocket<BoxTransport> {
+    /// Connect to `cargo fixture` using the Unix socket of the async runtime selected by a feature.
+    pub(crate) async fn connect(hello: Request) -> Result<Self> {
+        let stream = super::platform::connect(super::socket_path()?)
+            .await
+            .map_err(Error::RpcIo)?;
+        Self::connect_with(Box::new(stream), hello).await
+    }
+}
+
+impl<S> RpcSocket<S>
+where
+    S: Transport,
+{
+    /// Perform the client handshake over an already connected stream.
+    pub async fn connect_with(stream: S, hello: Request) -> Result<Self> {
+        let mut this = Self::new(stream);
+
+        // Perform handshake
+        let resp = this.call(hello).await?;
+        (this.protocol, this.capabilities) = handshake_result(resp)?;
+
+        Ok(this)
+    }
+
+    /// Create a socket speaking protocol 1, as is appropriate before the handshake.
+    pub fn new(stream: S) -> Self {
+        let (reader, writer) = stream.split();
+        let reader = Reader {
+            socket: BufReader::new(reader),
+            buffer: String::with_capacity(1024),
+        };
+        Self {
+            reader: AsyncMutex::new(reader),
+            writer: AsyncMutex::new(writer),
+            protocol: 1,
+            capabilities: vec![],
+            calls: Calls::default(),
+        }
+    }
+
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: u32) {
+        self.protocol = protocol;
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Send a raw message, without any ID.
+    pub async fn send<T>(&self, msg: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let msg = encode(msg)?;
+        self.writer
+            .lock()
+            .await
+            .write_all(msg.as_bytes())
+            .await
+            .map_err(Error::RpcIo)?;
+        Ok(())
+    }
+
+    /// Receive a raw message, returns `None` if the connection was closed.
+    pub async fn recv<T>(&self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.reader.lock().await.recv().await
+    }
+
+    /// Receive a request on the host side. The ID is always `None` in protocol 1.
+    pub async fn recv_request(&self) -> Result<Option<Envelope<Request>>> {
+        self.recv().await
+    }
+
+    /// Send a response to a request received with [`recv_request()`][RpcSocket::recv_request].
+    pub async fn respond(&self, id: Option<u64>, msg: Response) -> Result<()> {
+        if self.protocol >= 2 {
+            self.send(Envelope { id, msg }).await
+        } else {
+            self.send(msg).await
+        }
+    }
+
+    pub async fn call(&self, request: Request) -> Result<Response> {
+        if self.protocol < 2 {
+            // Hold the reader while sending, so that calls are strictly one after another
+            let mut reader = self.reader.lock().await;
+            self.send(request).await?;
+            return reader.recv().await?.ok_or(Error::RpcHangup);
+        }
+
+        let call = self.calls.start();
+        self.send(Envelope {
+            id: Some(call.id),
+            msg: request,
+        })
+        .await?;
+
+        loop {
+            // Wait for either the response, read by another caller, or for the reader
+            let delivered = future::poll_fn(|cx| self.calls.poll_take(call.id, cx));
+            let reader = self.reader.lock();
+            pin_mut!(delivered, reader);
+            let mut reader = match future::select(delivered, reader).await {
+                Either::Left((resp, _)) => return Ok(resp),
+                Either::Right((reader, _)) => reader,
+            };
+            // The response may have been read by another caller while we were waiting for the reader
+            if let Some(resp) = self.calls.take(call.id) {
+                return Ok(resp);
+            }
+
+            let resp = reader.recv().await?.ok_or(Error::RpcHangup)?;
+            if let Some(resp) = self.calls.dispatch(call.id, resp) {
+                return Ok(resp);
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! The blocking socket, used by the blocking clients.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    sync::Mutex,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(windows)]
+use uds_windows::UnixStream;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    calls::{decode, encode, Calls},
+    handshake_result, socket_path, Capability, Envelope, Request, Response,
+};
+use crate::{Error, Result};
+
+#[derive(Debug)]
+struct Reader {
+    socket: BufReader<UnixStream>,
+    buffer: String,
+}
+
+impl Reader {
+    fn recv<T>(&mut self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.buffer.clear();
+        let num_read = self
+            .socket
+            .read_line(&mut self.buffer)
+            .map_err(Error::RpcIo)?;
+        decode(num_read, &self.buffer)
+    }
+}
+
+/// A blocking counterpart of the async `RpcSocket`, client side only.
+///
+/// All methods take `&self`, concurrent calls from multiple threads are supported.
+#[derive(Debug)]
+pub struct RpcSocket {
+    reader: Mutex<Reader>,
+    writer: Mutex<UnixStream>,
+    protocol: u32,
+    /// Capabilities of the host.
+    capabilities: Vec<Capability>,
+    calls: Calls,
+}
+
+impl RpcSocket {
+    pub(crate) fn connect(hello: Request) -> Result<Self> {
+        let stream = UnixStream::connect(socket_path()?).map_err(Error::RpcIo)?;
+        let mut this = Self::new(stream)?;
+
+        // Perform handshake
+        let resp = this.call(hello)?;
+        (this.protocol, this.capabilities) = handshake_result(resp)?;
+
+        Ok(this)
+    }
+
+    /// Create a socket speaking protocol 1, as is appropriate before the handshake.
+    pub fn new(stream: UnixStream) -> Result<Self> {
+        let writer = stream.try_clone().map_err(Error::RpcIo)?;
+        let reader = Reader {
+            socket: BufReader::new(stream),
+            buffer: String::with_capacity(1024),
+        };
+        Ok(Self {
+            reader: Mutex::new(reader),
+            writer: Mutex::new(writer),
+            protocol: 1,
+            capabilities: vec![],
+            calls: Calls::default(),
+        })
+    }
+
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Send a raw message, without any ID.
+    pub fn send<T>(&self, msg: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let msg = encode(msg)?;
+        self.writer
+            .lock()
+            .unwrap()
+            .write_all(msg.as_bytes())
+            .map_err(Error::RpcIo)
+    }
+
+    /// Receive a raw message, returns `None` if the connection was closed.
+    pub fn recv<T>(&self) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.reader.lock().unwrap().recv()
+    }
+
+    pub(crate) fn call(&self, request: Request) -> Result<Response> {
+        if self.protocol < 2 {
+            // Hold the reader while sending, so that calls are strictly one after another
+            let mut reader = self.reader.lock().unwrap();
+            self.send(request)?;
+            return reader.recv()?.ok_or(Error::RpcHangup);
+        }
+
+        let call = self.calls.start();
+        self.send(Envelope {
+            id: Some(call.id),
+            msg: request,
+        })?;
+
+        loop {
+            // Either the response was read by another caller, or it's our turn to read
+            if let Some(resp) = self.calls.wait(call.id) {
+                return Ok(resp);
+            }
+
+            // Hand over the response before letting others read, they'd miss it otherwise
+            let resp = self
+                .reader
+                .lock()
+                .unwrap()
+                .recv()
+                .and_then(|resp| resp.ok_or(Error::RpcHangup))
+                .map(|resp| self.calls.dispatch(call.id, resp));
+            self.calls.done_reading();
+            if let Some(resp) = resp? {
+                return Ok(resp);
+            }
+        }
+    }
+}
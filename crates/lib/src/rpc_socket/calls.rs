@@ -0,0 +1,224 @@
+//! Transport-independent parts of the sockets, shared by the async and the blocking one.
+
+#[cfg(feature = "futures-io")]
+use std::task::{Context, Poll};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Condvar, Mutex,
+    },
+    task::Waker,
+};
+
+use log::{debug, trace};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Envelope, Response};
+use crate::{Error, Result};
+
+/// Serialize a message into a line to be sent.
+pub(super) fn encode<T>(msg: T) -> Result<String>
+where
+    T: Serialize,
+{
+    let mut msg = serde_json::to_string(&msg).map_err(Error::RpcSerde)?;
+    trace!("RPC send: {}", super::redact(&msg));
+    msg.push('\n');
+    Ok(msg)
+}
+
+/// Deserialize a received line, `num_read` of zero means the connection was closed.
+pub(super) fn decode<T>(num_read: usize, line: &str) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    if num_read == 0 {
+        Ok(None)
+    } else {
+        let msg = line.trim();
+        trace!("RPC recv: {}", super::redact(msg));
+        serde_json::from_str(msg).map(Some).map_err(Error::RpcSerde)
+    }
+}
+
+/// Bookkeeping of protocol 2 calls: request IDs and responses read by one caller on behalf of another.
+///
+/// A caller holding the socket reader hands each response it reads to [`Calls::dispatch()`],
+/// which delivers responses to other calls straight to their callers: async callers are woken up
+/// (see [`Calls::poll_take()`]), blocking callers are waiting in [`Calls::wait()`].
+/// Callers don't have to get hold of the reader to pick up their response, so a call
+/// that's pending for a long time, such as `ready()`, doesn't hold up the others.
+#[derive(Default, Debug)]
+pub(super) struct Calls {
+    next_id: AtomicU64,
+    state: Mutex<State>,
+    /// Notified whenever a response is delivered or the blocking reader is released.
+    delivered: Condvar,
+}
+
+#[derive(Default, Debug)]
+struct State {
+    pending: HashMap<u64, Pending>,
+    /// Whether a blocking caller is reading from the socket.
+    #[cfg(feature = "blocking")]
+    reading: bool,
+}
+
+impl State {
+    fn take(&mut self, id: u64) -> Option<Response> {
+        self.pending
+            .get_mut(&id)
+            .and_then(|pending| pending.response.take())
+    }
+}
+
+#[derive(Default, Debug)]
+struct Pending {
+    response: Option<Response>,
+    waker: Option<Waker>,
+}
+
+impl Calls {
+    /// Start a new call, its response is delivered until the returned guard is dropped.
+    pub fn start(&self) -> CallGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .insert(id, Pending::default());
+        CallGuard { calls: self, id }
+    }
+
+    /// Take the response to call `id` if it's been delivered.
+    #[cfg(feature = "futures-io")]
+    pub fn take(&self, id: u64) -> Option<Response> {
+        self.state.lock().unwrap().take(id)
+    }
+
+    /// Like [`Calls::take()`], but registers the task to be woken up once the response is delivered.
+    #[cfg(feature = "futures-io")]
+    pub fn poll_take(&self, id: u64, cx: &mut Context<'_>) -> Poll<Response> {
+        let mut state = self.state.lock().unwrap();
+        let Some(pending) = state.pending.get_mut(&id) else {
+            return Poll::Pending;
+        };
+        match pending.response.take() {
+            Some(resp) => Poll::Ready(resp),
+            None => {
+                pending.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Block until the response to call `id` is delivered, or until it's the caller's turn to read
+    /// from the socket, in which case `None` is returned and the caller has to call [`Calls::done_reading()`]
+    /// once it's read a message.
+    #[cfg(feature = "blocking")]
+    pub fn wait(&self, id: u64) -> Option<Response> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(resp) = state.take(id) {
+                return Some(resp);
+            }
+            if !state.reading {
+                state.reading = true;
+                return None;
+            }
+            state = self.delivered.wait(state).unwrap();
+        }
+    }
+
+    /// Let another blocking caller read from the socket, see [`Calls::wait()`].
+    #[cfg(feature = "blocking")]
+    pub fn done_reading(&self) {
+        self.state.lock().unwrap().reading = false;
+        self.delivered.notify_all();
+    }
+
+    /// Handle a response read by the caller of call `id`, returns it if it's the response to that call,
+    /// otherwise it's delivered to its caller.
+    pub fn dispatch(&self, id: u64, resp: Envelope<Response>) -> Option<Response> {
+        let Envelope { id: resp_id, msg } = resp;
+        match resp_id {
+            Some(resp_id) if resp_id == id => return Some(msg),
+            Some(resp_id) => {
+                let mut state = self.state.lock().unwrap();
+                match state.pending.get_mut(&resp_id) {
+                    Some(pending) => {
+                        pending.response = Some(msg);
+                        if let Some(waker) = pending.waker.take() {
+                            waker.wake();
+                        }
+                        self.delivered.notify_all();
+                    }
+                    // The call was cancelled
+                    None => debug!("ignoring response to call {resp_id}: {msg:?}"),
+                }
+            }
+            // No notifications are defined yet
+            None => debug!("ignoring RPC notification: {msg:?}"),
+        }
+        None
+    }
+}
+
+/// A call in progress, see [`Calls::start()`].
+pub(super) struct CallGuard<'a> {
+    calls: &'a Calls,
+    pub id: u64,
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        self.calls.state.lock().unwrap().pending.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Calls, Envelope, Response};
+
+    #[test]
+    fn dispatch_delivers_other_responses() {
+        let calls = Calls::default();
+        let (first, second) = (calls.start(), calls.start());
+        assert_ne!(first.id, second.id);
+
+        let resp = Envelope {
+            id: Some(second.id),
+            msg: Response::Ok,
+        };
+        assert!(calls.dispatch(first.id, resp).is_none());
+        let notification = Envelope {
+            id: None,
+            msg: Response::Ok,
+        };
+        assert!(calls.dispatch(first.id, notification).is_none());
+
+        assert!(calls.state.lock().unwrap().take(first.id).is_none());
+        assert!(matches!(
+            calls.state.lock().unwrap().take(second.id),
+            Some(Response::Ok)
+        ));
+        assert!(calls.state.lock().unwrap().take(second.id).is_none());
+
+        let resp = Envelope {
+            id: Some(first.id),
+            msg: Response::Ok,
+        };
+        assert!(matches!(calls.dispatch(first.id, resp), Some(Response::Ok)));
+
+        // Responses to cancelled calls are dropped
+        let id = second.id;
+        drop(second);
+        let resp = Envelope {
+            id: Some(id),
+            msg: Response::Ok,
+        };
+        assert!(calls.dispatch(first.id, resp).is_none());
+        assert!(!calls.state.lock().unwrap().pending.contains_key(&id));
+    }
+}
@@ -1,19 +1,40 @@
+//! Unix socket types of the async runtime selected by a feature.
+//!
+//! All the `UnixStream` types implement the `futures-io` traits, Tokio's stream is wrapped with a compat layer.
+//! `UnixListener` is only provided with smol, which is what the `cargo fixture` host uses.
+
+#[cfg(unix)]
+use std::{io, path::PathBuf};
+
 // Unix
 #[cfg(all(unix, feature = "smol"))]
 pub use smol::net::unix::{UnixListener, UnixStream};
 #[cfg(all(unix, feature = "tokio"))]
-pub use tokio::net::{UnixListener, UnixStream};
+pub type UnixStream = tokio_util::compat::Compat<tokio::net::UnixStream>;
+#[cfg(all(unix, feature = "async-std"))]
+pub use async_std::os::unix::net::UnixStream;
+
+#[cfg(all(unix, any(feature = "smol", feature = "async-std")))]
+pub async fn connect(path: PathBuf) -> io::Result<UnixStream> {
+    UnixStream::connect(path).await
+}
 
-// Common
-#[cfg(feature = "smol")]
-pub use smol::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-#[cfg(feature = "tokio")]
-pub use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+#[cfg(all(unix, feature = "tokio"))]
+pub async fn connect(path: PathBuf) -> io::Result<UnixStream> {
+    use tokio_util::compat::TokioAsyncReadCompatExt as _;
+
+    tokio::net::UnixStream::connect(path)
+        .await
+        .map(|stream| stream.compat())
+}
 
 // Windows
-#[cfg(windows)]
+#[cfg(all(windows, any(feature = "smol", feature = "tokio")))]
 pub use windows::*;
 
+#[cfg(all(windows, feature = "async-std"))]
+compile_error!("The async-std feature is not supported on Windows, use the futures-io feature with a custom stream instead");
+
 #[cfg(all(windows, feature = "smol"))]
 mod windows {
     //! A wrapper around `uds_windows` using smol's `Async` feature.
@@ -55,13 +76,17 @@ mod windows {
             smol::unblock(|| uds_windows::UnixStream::connect(path).and_then(Async::new))
         }
     }
+
+    pub async fn connect(path: PathBuf) -> io::Result<UnixStream> {
+        UnixStream::connect(path).await
+    }
 }
 
 #[cfg(all(windows, feature = "tokio"))]
 mod windows {
     //! Since Tokio doesn't have a counterpart to smol's `Async`, this is a bit more cumbersome.
     //!
-    //! `accept()` and `connect()` are implemented `spawn_blocking()`, while
+    //! `connect()` is implemented using `spawn_blocking()`, while
     //! async read and write on the Stream are implemented by turning the handle into
     //! `TcpStream` unsafely. This is not exposed and only read/write are performed,
     //! which should be compatible.
@@ -70,9 +95,8 @@ mod windows {
         io,
         net::TcpStream as StdTcpStream,
         os::windows::io::{FromRawSocket as _, IntoRawSocket as _},
-        path::{Path, PathBuf},
+        path::PathBuf,
         pin::Pin,
-        sync::Mutex,
         task::{Context, Poll},
     };
 
@@ -82,44 +106,22 @@ mod windows {
         pin,
         task::spawn_blocking,
     };
-    use uds_windows::SocketAddr;
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt as _};
 
-    #[derive(Debug)]
-    pub struct UnixListener {
-        sync: Mutex<Option<uds_windows::UnixListener>>,
-    }
+    pub type UnixStream = Compat<TokioUnixStream>;
 
-    impl UnixListener {
-        pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
-            let sync = uds_windows::UnixListener::bind(path)
-                .map(Some)
-                .map(Mutex::new)?;
-            Ok(Self { sync })
-        }
-
-        pub async fn accept(&self) -> io::Result<(UnixStream, SocketAddr)> {
-            let mut lock = self.sync.lock().unwrap();
-            let srv_socket = lock.take().unwrap();
-            let (srv_socket, res) = spawn_blocking(move || {
-                let res = srv_socket.accept();
-                (srv_socket, res)
-            })
+    pub async fn connect(path: PathBuf) -> io::Result<UnixStream> {
+        TokioUnixStream::connect(path)
             .await
-            .unwrap();
-            *lock = Some(srv_socket);
-
-            let (acc_socket, addr) = res?;
-            let acc_socket = UnixStream::new(acc_socket)?;
-            Ok((acc_socket, addr))
-        }
+            .map(|stream| stream.compat())
     }
 
     #[derive(Debug)]
-    pub struct UnixStream {
+    pub struct TokioUnixStream {
         inner: TcpStream,
     }
 
-    impl UnixStream {
+    impl TokioUnixStream {
         fn new(socket: uds_windows::UnixStream) -> io::Result<Self> {
             socket.set_nonblocking(true)?;
             let socket = socket.into_raw_socket();
@@ -136,7 +138,7 @@ mod windows {
         }
     }
 
-    impl AsyncRead for UnixStream {
+    impl AsyncRead for TokioUnixStream {
         fn poll_read(
             mut self: Pin<&mut Self>,
             cx: &mut Context<'_>,
@@ -148,7 +150,7 @@ mod windows {
         }
     }
 
-    impl AsyncWrite for UnixStream {
+    impl AsyncWrite for TokioUnixStream {
         fn poll_write(
             mut self: Pin<&mut Self>,
             cx: &mut Context<'_>,
@@ -0,0 +1,192 @@
+use std::{thread, time::Duration};
+
+use crate::rpc_socket::{hello, ConnectionType, Request};
+#[cfg(any(
+    feature = "smol",
+    feature = "tokio",
+    feature = "async-std",
+    feature = "blocking"
+))]
+use crate::{rpc_socket::Capability, Error, Result};
+
+/// Options of a test connection, used with `TestClient::connect_with_options()`.
+///
+/// The [`with_fixture`][crate::with_fixture] macro sets these based on its arguments.
+#[derive(Clone, Default, Debug)]
+pub struct TestOptions {
+    serial: bool,
+    timeout: Option<Duration>,
+    name: Option<String>,
+}
+
+impl TestOptions {
+    /// Create default options, i.e. a non-serial test without a timeout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the test serial, see `TestClient::connect()`.
+    pub fn serial(mut self, serial: bool) -> Self {
+        self.serial = serial;
+        self
+    }
+
+    /// Set a time limit for the test connection.
+    ///
+    /// If the test is still connected once the time limit elapses, `cargo fixture` drops the connection
+    /// and fails the test run. This takes precedence over the `--test-conn-timeout` CLI flag.
+    ///
+    /// With an older `cargo fixture` binary, which would ignore the time limit,
+    /// connecting fails with [`Error::RpcUnsupported`][crate::Error::RpcUnsupported].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the name of the test as shown in `cargo fixture` logs.
+    ///
+    /// By default the name of the current thread is used, which is the test name when running under libtest.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub(crate) fn hello(&self) -> Request {
+        let mut hello = hello(ConnectionType::client(self.serial));
+        if let Request::Hello {
+            name, timeout_ms, ..
+        } = &mut hello
+        {
+            *name = self
+                .name
+                .clone()
+                .or_else(|| thread::current().name().map(ToOwned::to_owned));
+            *timeout_ms = self
+                .timeout
+                .map(|timeout| timeout.as_millis().try_into().unwrap_or(u64::MAX));
+        }
+        hello
+    }
+
+    /// Check that the host supports the options, given its capabilities.
+    #[cfg(any(
+        feature = "smol",
+        feature = "tokio",
+        feature = "async-std",
+        feature = "blocking"
+    ))]
+    pub(crate) fn check_supported(
+        &self,
+        has_capability: impl Fn(Capability) -> bool,
+    ) -> Result<()> {
+        if self.timeout.is_some() && !has_capability(Capability::TestTimeout) {
+            return Err(Error::RpcUnsupported(Capability::TestTimeout));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a duration such as `500ms`, `30s`, `5m` or `1h` into milliseconds, returns `None` if invalid or out of range.
+///
+/// This is shared by the `timeout` argument of [`with_fixture`][crate::with_fixture],
+/// where it's evaluated at compile time, and the `--test-conn-timeout` flag of `cargo fixture`.
+#[doc(hidden)]
+pub const fn parse_duration_ms(value: &str) -> Option<u64> {
+    let bytes = value.as_bytes();
+    let (mut start, mut end) = (0, bytes.len());
+    while start < end && bytes[start].is_ascii_whitespace() {
+        start += 1;
+    }
+    while end > start && bytes[end - 1].is_ascii_whitespace() {
+        end -= 1;
+    }
+
+    let mut num: u64 = 0;
+    let mut i = start;
+    while i < end && bytes[i].is_ascii_digit() {
+        num = match num.checked_mul(10) {
+            Some(num) => match num.checked_add((bytes[i] - b'0') as u64) {
+                Some(num) => num,
+                None => return None,
+            },
+            None => return None,
+        };
+        i += 1;
+    }
+    if i == start {
+        return None;
+    }
+    while i < end && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+
+    let multiplier = match end - i {
+        2 if bytes[i] == b'm' && bytes[i + 1] == b's' => 1,
+        1 => match bytes[i] {
+            b's' => 1000,
+            b'm' => 60 * 1000,
+            b'h' => 60 * 60 * 1000,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    num.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_duration_ms;
+
+    #[test]
+    fn duration_syntax() {
+        let table: &[(&str, Option<u64>)] = &[
+            ("500ms", Some(500)),
+            ("30s", Some(30_000)),
+            ("5m", Some(300_000)),
+            ("1h", Some(3_600_000)),
+            ("0s", Some(0)),
+            (" 10 s ", Some(10_000)),
+            ("30", None),
+            ("s", None),
+            ("", None),
+            ("-1s", None),
+            ("1.5s", None),
+            ("30sec", None),
+            ("1d", None),
+            ("30S", None),
+            ("18446744073709551615ms", Some(u64::MAX)),
+            ("18446744073709551616ms", None),
+            ("18446744073709551615h", None),
+        ];
+        for &(value, expected) in table {
+            assert_eq!(parse_duration_ms(value), expected, "{value:?}");
+        }
+    }
+
+    #[cfg(any(
+        feature = "smol",
+        feature = "tokio",
+        feature = "async-std",
+        feature = "blocking"
+    ))]
+    #[test]
+    fn timeout_requires_capability() {
+        use std::time::Duration;
+
+        use super::{Capability, TestOptions};
+        use crate::Error;
+
+        let options = TestOptions::new().serial(true);
+        assert!(options.check_supported(|_| false).is_ok());
+
+        let options = options.timeout(Duration::from_secs(30));
+        let err = options.check_supported(|_| false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RpcUnsupported(Capability::TestTimeout)
+        ));
+        assert!(options
+            .check_supported(|capability| capability == Capability::TestTimeout)
+            .is_ok());
+    }
+}
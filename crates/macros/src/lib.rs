@@ -3,7 +3,7 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use quote::ToTokens;
 use syn::parse_macro_input;
-use types::{Args, TestFn, WrappedFn};
+use types::{Args, FixtureMain, FixtureMod, TestFn, WithFixtureItem, WrappedFn};
 
 mod types;
 
@@ -22,13 +22,57 @@ macro_rules! tri {
 /// i.e. when not running under `cargo fixture`.
 /// It also wraps the function with a `TestClient` connection.
 ///
-/// The function's signature must be:
+/// The function's signature is typically:
 ///
-/// ```rust
+/// ```text
 /// async fn foo(client: TestClient)
 /// ```
 ///
-/// `#[with_fixture]` must come _before_ attributes like `#[tokio::test]`.
+/// ### Injected parameters
+/// K-V store values and environment variables can be requested as parameters directly using the `kv` and `env` attributes.
+/// The K-V values are deserialized, the env vars are parsed using `FromStr`. If a value is missing or invalid, the test panics.
+/// The parameters can be in any order and the `TestClient` parameter is optional:
+///
+/// ```text
+/// async fn foo(#[kv("db_uri")] uri: String, #[env("HTTP_PORT")] port: u16, client: TestClient)
+/// ```
+///
+/// The `TestClient` parameter is recognized by its type (`TestClient` or `blocking::TestClient`, possibly qualified),
+/// when using a type alias, mark it with `#[client]` instead.
+///
+/// ### Parameterized tests
+/// Any other parameters are left to other attributes, such as `#[rstest]` or `#[test_case(...)]`,
+/// which makes parameterized tests work as usual. The `#[ignore]` marker is applied to each generated test case:
+///
+/// ```rust,ignore
+/// #[with_fixture]
+/// #[rstest]
+/// #[case(1)]
+/// #[case(2)]
+/// #[tokio::test]
+/// async fn foo(#[case] input: u32, #[kv("db_uri")] uri: String)
+/// ```
+///
+/// `#[with_fixture]` must come _before_ attributes like `#[tokio::test]` or `#[rstest]`,
+/// as it only sees what they expand to otherwise. Placing it after them is a compile error.
+/// The test attribute is recognized by its name (`test`, `rstest` or `test_*`), for test attributes with other names,
+/// such as `#[wasm_bindgen_test]`, turn the check off with `test_attr_checked = false`:
+///
+/// ```rust,ignore
+/// #[with_fixture(test_attr_checked = false)]
+/// #[wasm_bindgen_test]
+/// async fn foo(client: TestClient)
+/// ```
+///
+/// ### Synchronous tests
+/// The attribute can also be applied to a regular non-async `#[test]` function,
+/// in which case the blocking client is used (this requires the `blocking` feature of `cargo-fixture-lib`):
+///
+/// ```rust,ignore
+/// #[with_fixture]
+/// #[test]
+/// fn foo(client: blocking::TestClient)
+/// ```
 ///
 /// ### Serial connection
 /// To have the `TestClient` connected with `serial` set to `true`, use the `serial` syntax:
@@ -37,20 +81,89 @@ macro_rules! tri {
 /// #[with_fixture(serial)]
 /// ```
 ///
+/// ### Timeout
+/// To have `cargo fixture` fail the test run when the test stays connected for too long, use the `timeout` argument.
+/// The connection is then dropped, which also lets other tests waiting for a serial test to proceed.
+/// The value is a number with a unit, one of `ms`, `s`, `m` or `h`:
+///
+/// ```rust,ignore
+/// #[with_fixture(serial, timeout = "30s")]
+/// ```
+///
+/// This overrides the `--test-conn-timeout` flag of `cargo fixture`.
+///
+/// ### Modules
+/// The attribute can also be applied to an inline module, in which case all the test functions in it
+/// (i.e. those with a `#[test]`, `#[tokio::test]`, `#[rstest]`, `#[test_case]` or similar attribute) are wrapped,
+/// including those in nested modules. Arguments such as `serial` apply to all of them.
+/// Use `#[without_fixture]` to opt a function out:
+///
+/// ```rust,ignore
+/// #[with_fixture(serial)]
+/// mod fixture_tests {
+///     #[tokio::test]
+///     async fn foo(client: TestClient) {}
+///
+///     #[without_fixture]
+///     #[test]
+///     fn bar() {}
+/// }
+/// ```
+///
 /// ## Example
 ///
 /// ```
+/// # use cargo_fixture::{with_fixture, TestClient};
+/// # use serde_json::Value;
 /// #[with_fixture]
 /// #[tokio::test]
-/// async fn with_fixture_example(mut client: TestClient) {
+/// async fn with_fixture_example(client: TestClient) {
 ///     let example: Value = client.get_value("example").await.unwrap();
 /// }
 /// ```
 #[proc_macro_attribute]
 pub fn with_fixture(args: TokenStream, input: TokenStream) -> TokenStream {
     let args = parse_macro_input!(args as Args);
-    let test_fn = parse_macro_input!(input as TestFn);
-    tri!(WrappedFn::wrap(test_fn, args))
-        .into_token_stream()
-        .into()
+    match parse_macro_input!(input as WithFixtureItem) {
+        WithFixtureItem::Fn(test_fn) => tri!(WrappedFn::wrap(*test_fn, args))
+            .into_token_stream()
+            .into(),
+        WithFixtureItem::Mod(module) => tri!(FixtureMod::wrap(module, args))
+            .into_token_stream()
+            .into(),
+    }
+}
+
+/// Generate the `main()` function of a fixture program.
+///
+/// The annotated function performs the fixture setup and returns the cleanup code to be run after tests.
+/// Its signature must be:
+///
+/// ```rust,ignore
+/// async fn main(fixture: &mut FixtureClient) -> Result<Cleanup, E>
+/// ```
+///
+/// where `E` is any error type implementing `Debug`, such as `anyhow::Error`.
+///
+/// The generated `main()` function sets up the async runtime selected by the `cargo-fixture-lib` feature,
+/// connects to `cargo fixture`, runs the setup, starts the tests using `FixtureClient::ready()`,
+/// and then runs the cleanup. The cleanup is run even if the test run fails with an error or a panic.
+/// Errors are reported and turned into a non-zero exit code.
+///
+/// ## Example
+///
+/// ```rust,ignore
+/// #[cargo_fixture::fixture_main]
+/// async fn main(fixture: &mut FixtureClient) -> anyhow::Result<Cleanup> {
+///     let server = start_server().await?;
+///     fixture.set_env_var("SERVER_ADDR", server.addr()).await?;
+///
+///     Ok(Cleanup::new(|success| async move { server.shutdown().await }))
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn fixture_main(args: TokenStream, input: TokenStream) -> TokenStream {
+    let _ = parse_macro_input!(args as syn::parse::Nothing);
+    let main_fn = parse_macro_input!(input as TestFn);
+    tri!(FixtureMain::wrap(main_fn)).into_token_stream().into()
 }
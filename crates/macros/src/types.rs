@@ -1,20 +1,38 @@
-use proc_macro2::{Ident, TokenStream};
-use quote::{quote, ToTokens};
+use std::mem;
+
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 use syn::{
     parse::{Parse, ParseStream},
+    parse_quote,
     spanned::Spanned,
-    Attribute, Error, Result, Signature, Visibility,
+    Attribute, Error, FnArg, Item, ItemFn, ItemMod, LitBool, LitStr, Pat, Result, Signature, Token,
+    Type, Visibility,
 };
 
 mod kw {
     syn::custom_keyword!(serial);
+    syn::custom_keyword!(timeout);
+    syn::custom_keyword!(test_attr_checked);
 }
 
+#[derive(Clone, Default)]
 pub struct Args {
     serial: Option<kw::serial>,
+    /// Test connection time limit, such as `"30s"`, parsed at compile time by the generated code.
+    timeout: Option<LitStr>,
+    /// `test_attr_checked = false` turns off the check for a test attribute after `#[with_fixture]`,
+    /// for test attributes not recognized by their name.
+    test_attr_checked: Option<LitBool>,
 }
 
 impl Args {
+    fn test_attr_checked(&self) -> bool {
+        self.test_attr_checked
+            .as_ref()
+            .map_or(true, |checked| checked.value)
+    }
+
     fn serial_as_bool(&self) -> Ident {
         let value = if self.serial.is_some() {
             "true"
@@ -27,9 +45,37 @@ impl Args {
 
 impl Parse for Args {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        Ok(Self {
-            serial: input.parse()?,
-        })
+        let mut args = Self::default();
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::serial) {
+                let serial = input.parse::<kw::serial>()?;
+                if args.serial.replace(serial).is_some() {
+                    return Err(Error::new_spanned(serial, "duplicate argument"));
+                }
+            } else if lookahead.peek(kw::timeout) {
+                let kw = input.parse::<kw::timeout>()?;
+                input.parse::<Token![=]>()?;
+                let value = input.parse::<LitStr>()?;
+                if args.timeout.replace(value).is_some() {
+                    return Err(Error::new_spanned(kw, "duplicate argument"));
+                }
+            } else if lookahead.peek(kw::test_attr_checked) {
+                let kw = input.parse::<kw::test_attr_checked>()?;
+                input.parse::<Token![=]>()?;
+                let value = input.parse::<LitBool>()?;
+                if args.test_attr_checked.replace(value).is_some() {
+                    return Err(Error::new_spanned(kw, "duplicate argument"));
+                }
+            } else {
+                return Err(lookahead.error());
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
     }
 }
 
@@ -56,6 +102,17 @@ impl Parse for TestFn {
     }
 }
 
+impl From<ItemFn> for TestFn {
+    fn from(item: ItemFn) -> Self {
+        Self {
+            attrs: item.attrs,
+            vis: item.vis,
+            sig: item.sig,
+            block: item.block.into_token_stream(),
+        }
+    }
+}
+
 impl ToTokens for TestFn {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let Self {
@@ -72,21 +129,158 @@ impl ToTokens for TestFn {
     }
 }
 
+/// Where the value of a test fn parameter comes from.
+enum ParamSource {
+    /// The `TestClient` itself, recognized by type or the `#[client]` marker.
+    Client,
+    /// A K-V store value, `#[kv("key")]`.
+    KeyValue(LitStr),
+    /// An environment variable, `#[env("NAME")]`.
+    EnvVar(LitStr),
+    /// Any other parameter, such as `rstest` or `test-case` parameters, is a parameter of the wrapping fn as well.
+    /// The attributes are moved to the wrapping fn's parameter.
+    Passthrough { ident: Ident, attrs: Vec<Attribute> },
+}
+
+struct Param {
+    source: ParamSource,
+    ty: Type,
+}
+
+impl Param {
+    /// Parse the parameter and remove our attributes from it, so that the fn can be emitted as is.
+    fn parse(arg: &mut FnArg, index: usize) -> Result<Self> {
+        let FnArg::Typed(arg) = arg else {
+            return Err(Error::new_spanned(
+                arg,
+                "a with_fixture test function can't take self",
+            ));
+        };
+
+        let mut source = None;
+        let mut other_attrs = vec![];
+        for attr in mem::take(&mut arg.attrs) {
+            let new_source = if attr.path().is_ident("kv") {
+                ParamSource::KeyValue(attr.parse_args()?)
+            } else if attr.path().is_ident("env") {
+                ParamSource::EnvVar(attr.parse_args()?)
+            } else if attr.path().is_ident("client") {
+                attr.meta.require_path_only()?;
+                ParamSource::Client
+            } else {
+                other_attrs.push(attr);
+                continue;
+            };
+
+            if source.replace(new_source).is_some() {
+                return Err(Error::new_spanned(attr, "duplicate parameter attribute"));
+            }
+        }
+
+        let source = match source {
+            Some(source) => {
+                arg.attrs = other_attrs;
+                source
+            }
+            None if is_test_client(&arg.ty) => {
+                arg.attrs = other_attrs;
+                ParamSource::Client
+            }
+            None => {
+                let ident = match &*arg.pat {
+                    // Keep the name, rstest uses it to look up fixtures
+                    Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                        pat.ident.clone()
+                    }
+                    _ => format_ident!("param{index}", span = Span::mixed_site()),
+                };
+                ParamSource::Passthrough {
+                    ident,
+                    attrs: other_attrs,
+                }
+            }
+        };
+
+        Ok(Self {
+            source,
+            ty: (*arg.ty).clone(),
+        })
+    }
+
+    /// The parameter of the wrapping fn, if any.
+    fn wrapper_param(&self) -> Option<TokenStream> {
+        let ParamSource::Passthrough { ident, attrs } = &self.source else {
+            return None;
+        };
+        let ty = &self.ty;
+        Some(quote!(#(#attrs)* #ident: #ty))
+    }
+
+    /// Code evaluating to the parameter's value, `client` is the connected `TestClient`.
+    fn value(&self, client: &Ident, is_async: bool) -> TokenStream {
+        let ty = &self.ty;
+        let await_ = is_async.then(|| quote!(.await));
+        match &self.source {
+            ParamSource::Client => quote!(#client),
+            ParamSource::Passthrough { ident, .. } => quote!(#ident),
+            ParamSource::KeyValue(key) => quote! {
+                #client.get_value::<#ty>(#key)
+                    #await_
+                    .unwrap_or_else(|err| panic!("Could not get K-V value `{}`: {}", #key, err))
+            },
+            ParamSource::EnvVar(name) => quote! {
+                ::std::env::var(#name)
+                    .unwrap_or_else(|err| panic!("Could not get env var `{}`: {}", #name, err))
+                    .parse::<#ty>()
+                    .unwrap_or_else(|err| panic!("Could not parse env var `{}`: {}", #name, err))
+            },
+        }
+    }
+}
+
 pub struct WrappedFn {
     test_fn: TestFn,
     args: Args,
+    params: Vec<Param>,
 }
 
 impl WrappedFn {
-    pub fn wrap(test_fn: TestFn, args: Args) -> Result<Self> {
-        if test_fn.sig.inputs.len() == 1 {
-            Ok(Self { test_fn, args })
-        } else {
-            Err(Error::new_spanned(
+    pub fn wrap(mut test_fn: TestFn, args: Args) -> Result<Self> {
+        // Attributes before #[with_fixture] are expanded first and are gone by now,
+        // such as #[tokio::test] or the #[test] emitted by #[rstest] or #[test_case]
+        if args.test_attr_checked() && !test_fn.attrs.iter().any(is_test_attr) {
+            return Err(Error::new_spanned(
+                &test_fn.sig.ident,
+                "no test attribute found after #[with_fixture], \
+                place #[with_fixture] before attributes like #[test], #[tokio::test], #[rstest] or #[test_case], \
+                or use #[with_fixture(test_attr_checked = false)] if the test attribute has another name",
+            ));
+        }
+
+        let params = test_fn
+            .sig
+            .inputs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, arg)| Param::parse(arg, i))
+            .collect::<Result<Vec<_>>>()?;
+
+        let clients = params
+            .iter()
+            .filter(|param| matches!(param.source, ParamSource::Client))
+            .count();
+        if clients > 1 {
+            return Err(Error::new_spanned(
                 test_fn.sig.inputs,
-                "a with_fixture test function has to take one argument of type TestClient",
-            ))
+                "a with_fixture test function can take at most one argument of type TestClient",
+            ));
         }
+
+        Ok(Self {
+            test_fn,
+            args,
+            params,
+        })
     }
 }
 
@@ -99,32 +293,312 @@ impl ToTokens for WrappedFn {
     where
         Self: Sized,
     {
-        let Self { mut test_fn, args } = self;
+        let Self {
+            mut test_fn,
+            args,
+            params,
+        } = self;
         let serial = args.serial_as_bool();
+        // The timeout is parsed by the library in a const, so that the syntax is the same as that of the CLI flag
+        let timeout = args.timeout.map(|value| {
+            quote_spanned! {value.span()=>
+                .timeout(::std::time::Duration::from_millis({
+                    const TIMEOUT_MS: u64 = match ::cargo_fixture::parse_duration_ms(#value) {
+                        ::core::option::Option::Some(millis) => millis,
+                        ::core::option::Option::None => ::core::panic!(
+                            "invalid timeout, expected a number with a unit, such as \"500ms\", \"30s\", \"5m\" or \"1h\""
+                        ),
+                    };
+                    TIMEOUT_MS
+                }))
+            }
+        });
+        let options = quote!(::cargo_fixture::TestOptions::new().serial(#serial) #timeout);
+        let is_async = test_fn.sig.asyncness.is_some();
+        let uses_client = params
+            .iter()
+            .any(|param| matches!(param.source, ParamSource::Client | ParamSource::KeyValue(_)));
+        let client = if uses_client { "client" } else { "_client" };
+        let client = Ident::new(client, Span::mixed_site());
+
+        let arg_idents = (0..params.len())
+            .map(|i| format_ident!("arg{i}", span = Span::mixed_site()))
+            .collect::<Vec<_>>();
+        // The client is moved last, after it's been used to fetch K-V values
+        let mut arg_order = (0..params.len()).collect::<Vec<_>>();
+        arg_order.sort_by_key(|&i| matches!(params[i].source, ParamSource::Client));
+        let arg_lets = arg_order.into_iter().map(|i| {
+            let ident = &arg_idents[i];
+            let value = params[i].value(&client, is_async);
+            quote!(let #ident = #value;)
+        });
 
         // Generate the wrapping fn
         let mut wrapper_sig = test_fn.sig.clone();
         wrapper_sig.generics = Default::default();
-        wrapper_sig.inputs = Default::default();
-        let wrapper_attrs = test_fn.attrs.clone();
-        test_fn.attrs = Default::default();
+        wrapper_sig.inputs = params
+            .iter()
+            .filter_map(Param::wrapper_param)
+            .map(|param| syn::parse2::<FnArg>(param).expect("valid fn parameter"))
+            .collect();
+        // The ignore attribute goes last, so that it is propagated by attributes
+        // that generate test cases, such as `#[rstest]` or `#[test_case]`
+        let mut wrapper_attrs = mem::take(&mut test_fn.attrs);
+        wrapper_attrs.push(parse_quote!(
+            #[cfg_attr(not(feature = "_fixture"), ignore = "only ran under cargo fixture")]
+        ));
         let test_fn_ident = &test_fn.sig.ident;
+        // NB. the client is kept connected until the test fn returns even if it doesn't take it
+        let block = if is_async {
+            quote! {{
+                #test_fn
+                let #client = ::cargo_fixture::TestClient::connect_with_options(#options)
+                    .await
+                    .expect("Could not connect to cargo fixture");
+                #(#arg_lets)*
+                #test_fn_ident(#(#arg_idents),*).await
+            }}
+        } else {
+            // Synchronous test fn, use the blocking client
+            quote! {{
+                #test_fn
+                let #client = ::cargo_fixture::blocking::TestClient::connect_with_options(#options)
+                    .expect("Could not connect to cargo fixture");
+                #(#arg_lets)*
+                #test_fn_ident(#(#arg_idents),*)
+            }}
+        };
         let wrapper_fn = TestFn {
             attrs: wrapper_attrs,
             vis: test_fn.vis.clone(),
             sig: wrapper_sig,
-            block: quote! {{
-                #test_fn
-                let client = ::cargo_fixture::TestClient::connect(#serial)
-                    .await
-                    .expect("Could not connect to cargo fixture");
-                #test_fn_ident(client).await
-            }},
+            block,
         };
 
-        quote! {
-            #[cfg_attr(not(feature = "_fixture"), ignore = "only ran under cargo fixture")]
-            #wrapper_fn
+        wrapper_fn.into_token_stream()
+    }
+}
+
+/// An item `#[with_fixture]` can be applied to.
+pub enum WithFixtureItem {
+    Fn(Box<TestFn>),
+    Mod(ItemMod),
+}
+
+impl Parse for WithFixtureItem {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let fork = input.fork();
+        fork.call(Attribute::parse_outer)?;
+        fork.parse::<Visibility>()?;
+        if fork.peek(Token![mod]) {
+            input.parse().map(Self::Mod)
+        } else {
+            input.parse().map(Box::new).map(Self::Fn)
+        }
+    }
+}
+
+/// An inline module with `#[with_fixture]` applied to all its test fns.
+pub struct FixtureMod {
+    module: ItemMod,
+}
+
+impl FixtureMod {
+    pub fn wrap(mut module: ItemMod, args: Args) -> Result<Self> {
+        // Test fns in the module are recognized by their test attribute
+        if let Some(checked) = &args.test_attr_checked {
+            return Err(Error::new_spanned(
+                checked,
+                "test_attr_checked can't be used on a module, use it on the test function instead",
+            ));
+        }
+        let Some((_, items)) = module.content.as_mut() else {
+            return Err(Error::new_spanned(
+                module,
+                "with_fixture can only be applied to inline modules",
+            ));
+        };
+        Self::wrap_items(items, &args)?;
+        Ok(Self { module })
+    }
+
+    fn wrap_items(items: &mut [Item], args: &Args) -> Result<()> {
+        for item in items {
+            match item {
+                Item::Fn(item_fn) => {
+                    // Fns with their own #[with_fixture] are expanded separately with their own args
+                    if remove_attr(&mut item_fn.attrs, "without_fixture")
+                        || has_attr(&item_fn.attrs, "with_fixture")
+                        || !item_fn.attrs.iter().any(is_test_attr)
+                    {
+                        continue;
+                    }
+
+                    let test_fn = TestFn::from(item_fn.clone());
+                    let wrapped = WrappedFn::wrap(test_fn, args.clone())?;
+                    *item = Item::Verbatim(wrapped.into_token_stream());
+                }
+
+                Item::Mod(module) if !has_attr(&module.attrs, "with_fixture") => {
+                    if let Some((_, items)) = module.content.as_mut() {
+                        Self::wrap_items(items, args)?;
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ToTokens for FixtureMod {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.module.to_tokens(tokens);
+    }
+}
+
+/// Whether there is an attribute with the given name, matching only the last path segment,
+/// so that e.g. `test` matches `#[test]`, `#[tokio::test]` and `#[smol_potat::test]`.
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| attr_is(attr, name))
+}
+
+/// Remove attributes with the given name, returns whether any were removed.
+fn remove_attr(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+    let len = attrs.len();
+    attrs.retain(|attr| !attr_is(attr, name));
+    attrs.len() != len
+}
+
+/// Whether the type is `TestClient`, matching only the last path segment, i.e. also `blocking::TestClient`.
+fn is_test_client(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .map_or(false, |segment| segment.ident == "TestClient")
+}
+
+/// Whether the attribute makes a test out of a fn, such as `#[test]`, `#[tokio::test]`, `#[rstest]` or `#[test_case]`.
+fn is_test_attr(attr: &Attribute) -> bool {
+    attr.path().segments.last().map_or(false, |segment| {
+        let name = segment.ident.to_string();
+        name == "test" || name == "rstest" || name.starts_with("test_")
+    })
+}
+
+fn attr_is(attr: &Attribute, name: &str) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .map_or(false, |segment| segment.ident == name)
+}
+
+pub struct FixtureMain {
+    main_fn: TestFn,
+}
+
+impl FixtureMain {
+    pub fn wrap(main_fn: TestFn) -> Result<Self> {
+        if main_fn.sig.asyncness.is_none() {
+            return Err(Error::new_spanned(
+                main_fn.sig.fn_token,
+                "a fixture_main function has to be async",
+            ));
+        }
+        if main_fn.sig.inputs.len() != 1 {
+            return Err(Error::new_spanned(
+                main_fn.sig.inputs,
+                "a fixture_main function has to take one argument of type &mut FixtureClient",
+            ));
         }
+
+        Ok(Self { main_fn })
+    }
+}
+
+impl ToTokens for FixtureMain {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self { main_fn } = self;
+        let main_ident = &main_fn.sig.ident;
+        let fixture = Ident::new("fixture", Span::mixed_site());
+
+        // The user's fn is moved inside the generated one, attributes stay with the generated fn
+        let attrs = &main_fn.attrs;
+        let vis = &main_fn.vis;
+        let inner_fn = TestFn {
+            attrs: vec![],
+            vis: Visibility::Inherited,
+            sig: main_fn.sig.clone(),
+            block: main_fn.block.clone(),
+        };
+
+        tokens.extend(quote! {
+            #(#attrs)*
+            #vis fn #main_ident() -> ::std::process::ExitCode {
+                #inner_fn
+                ::cargo_fixture::fixture_runtime::block_on(async {
+                    let mut #fixture = ::cargo_fixture::fixture_runtime::connect().await;
+                    let setup = #main_ident(&mut #fixture).await;
+                    ::cargo_fixture::fixture_runtime::run(#fixture, setup).await
+                })
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::{Args, TestFn, WrappedFn};
+
+    fn wrap(test_fn: TestFn) -> Result<(), String> {
+        WrappedFn::wrap(test_fn, Args::default())
+            .map(drop)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_attr_order() {
+        wrap(parse_quote! {
+            #[tokio::test]
+            async fn foo(client: TestClient) {}
+        })
+        .unwrap();
+        wrap(parse_quote! {
+            #[rstest]
+            #[case(1)]
+            #[smol_potat::test]
+            async fn foo(#[case] input: u32) {}
+        })
+        .unwrap();
+        wrap(parse_quote! {
+            #[test_case(1 ; "one")]
+            fn foo(input: u32) {}
+        })
+        .unwrap();
+
+        // #[with_fixture] placed after the test attribute, e.g. `#[smol_potat::test] #[with_fixture]`,
+        // sees the already expanded test fn
+        let err = wrap(parse_quote! {
+            fn foo() {
+                ::smol_potat::async_io::block_on(async {})
+            }
+        })
+        .unwrap_err();
+        assert!(err.contains("place #[with_fixture] before"), "{err}");
+
+        // The same with the test fns generated by `#[test_case(...)] #[with_fixture]`
+        let err = wrap(parse_quote! {
+            #[allow(clippy::bool_assert_comparison)]
+            fn test_case_1_one() {}
+        })
+        .unwrap_err();
+        assert!(err.contains("place #[with_fixture] before"), "{err}");
     }
 }
@@ -1,8 +1,11 @@
-use std::{env, ffi::OsString, process};
+use std::{env, ffi::OsString, path::PathBuf, process};
 
 use anyhow::{bail, Result};
+use strum::{AsRefStr, Display, EnumIter, EnumMessage, EnumString};
 
-use crate::logger::LogLevel;
+use crate::{
+    coverage::CoverageFormat, env_export::EnvFormat, logger::LogLevel, utils::HumanDuration,
+};
 
 mod flags;
 use flags::def_flags;
@@ -18,6 +21,23 @@ def_flags!(
     --shell               set_flag(shell) "Run $SHELL instead of running cargo test",
     --nextest             set_flag(nextest) "Use cargo nextest instead of cargo test",
     -L [level]            parse_value(log_level) "Stderr logging level (choices: off, info, debug, trace, default: info)",
+    --event-log [path]    parse_opt_value(event_log) "Write a JSON Lines log of session events to the specified file",
+    --timings-json [path] parse_opt_value(timings_json) "Write phase timings summary as JSON to the specified file",
+    --test-conn-timeout [duration] parse_opt_value(test_conn_timeout) "Fail the run if a test stays connected for longer than this, e.g. 30s (default: no limit)",
+    --fail-on-leaks set_flag(fail_on_leaks) "Fail the run if test connections are left open after the test command exits",
+    --shell-on-failure    set_flag(shell_on_failure) "When tests fail, run $SHELL with the fixture environment before letting the fixture clean up",
+    --pause-before-cleanup set_flag(pause_before_cleanup) "When tests fail, wait for Enter or Ctrl+C before letting the fixture clean up",
+    --format [format]     parse_opt_value(env_format) "Output format of the env command (choices: sh, dotenv, json, default: sh)",
+    -o --output [path]    parse_opt_value(env_output) "Write the output of the env command to the specified file instead of stdout",
+    --record [path]       parse_opt_value(record) "Save the environment, K-V data and test command set up by the fixture to a file",
+    --replay [path]       parse_opt_value(replay) "Run tests with the values saved using --record, without building or running the fixture",
+    --no-fixture          set_flag(no_fixture) "Don't build or run a fixture program, serve tests with the values from --kv-file and --env-file",
+    --kv-file [path]      parse_opt_value(kv_file) "With --no-fixture, load the K-V store from a JSON object in the specified file",
+    --env-file [path]     parse_opt_value(env_file) "With --no-fixture, set the environment variables listed in the specified .env file",
+    --coverage            set_flag(coverage) "Build the fixture and tests with coverage instrumentation and write a report to target/coverage",
+    --coverage-format [format] parse_opt_value(coverage_format) "Format of the --coverage report (choices: lcov, html, default: lcov)",
+    --coverage-fixture    set_flag(coverage_fixture) "Include the fixture program in the --coverage report",
+    --dry-run             set_flag(dry_run) "Print the commands that would be run and how arguments were classified, then exit",
     -h --help             help "Print help",
     --version             version "Print version",
 );
@@ -50,20 +70,104 @@ def_flags!(
     --target-dir [DIR]       forward_value(cargo_common_test),
     --unit-graph             forward(cargo_common_test),
     --timings [FORMATS]      forward_value(cargo_common_test),
+
+    // Target selection, passed to the test command only
+    --lib                    forward(cargo_test_args),
+    --bins                   forward(cargo_test_args),
+    --bin [NAME]             forward_value(cargo_test_args),
+    --examples               forward(cargo_test_args),
+    --example [NAME]         forward_value(cargo_test_args),
+    --benches                forward(cargo_test_args),
+    --bench [NAME]           forward_value(cargo_test_args),
+    --all-targets            forward(cargo_test_args),
 );
 
+/// Recognized only as the first argument, otherwise arguments are passed to `cargo test`.
+#[derive(
+    EnumString, EnumIter, EnumMessage, AsRefStr, PartialEq, Eq, Clone, Copy, Default, Debug,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Subcommand {
+    /// Run the fixture and tests, the default.
+    #[default]
+    #[strum(disabled)]
+    Test,
+    #[strum(
+        message = "Print the state of running cargo fixture sessions (env vars, K-V store, tests, timings)"
+    )]
+    Status,
+    #[strum(
+        message = "Run the fixture and print its environment instead of running tests, keep it running until Ctrl+C"
+    )]
+    Env,
+    #[strum(message = "Run the fixture and cargo bench")]
+    Bench,
+    #[strum(
+        message = "Run the fixture and a binary or example using cargo run, e.g. run --bin <name> -- <args...>"
+    )]
+    Run,
+}
+
+impl Subcommand {
+    /// Whether the subcommand runs a cargo command after the fixture is ready.
+    pub fn runs_cargo(self) -> bool {
+        matches!(self, Self::Test | Self::Bench | Self::Run)
+    }
+}
+
+/// Where a command line argument was sorted to, reported by `--dry-run`.
+#[derive(Display, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ArgDest {
+    #[strum(to_string = "subcommand")]
+    Subcommand,
+    #[strum(to_string = "cargo fixture option")]
+    CargoFixture,
+    #[strum(to_string = "fixture program argument")]
+    Fixture,
+    #[strum(to_string = "cargo option, passed to cargo metadata and cargo test")]
+    CargoAll,
+    #[strum(to_string = "cargo option, passed to the fixture build and cargo test")]
+    CargoTest,
+    #[strum(to_string = "cargo test argument")]
+    CargoTestArgs,
+    #[strum(to_string = "test binary argument")]
+    Harness,
+    #[strum(to_string = "test command")]
+    Exec,
+}
+
 #[derive(Debug)]
 pub struct Cli {
+    pub subcommand: Subcommand,
     pub fixture_name: String,
     pub fixture_args: Vec<OsString>,
     pub exec: Vec<OsString>,
     pub shell: bool,
     pub nextest: bool,
     pub log_level: LogLevel,
+    pub event_log: Option<PathBuf>,
+    pub timings_json: Option<PathBuf>,
+    pub test_conn_timeout: Option<HumanDuration>,
+    pub fail_on_leaks: bool,
+    pub shell_on_failure: bool,
+    pub pause_before_cleanup: bool,
+    pub dry_run: bool,
+    pub env_format: Option<EnvFormat>,
+    pub env_output: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub no_fixture: bool,
+    pub kv_file: Option<PathBuf>,
+    pub env_file: Option<PathBuf>,
+    pub coverage: bool,
+    pub coverage_format: Option<CoverageFormat>,
+    pub coverage_fixture: bool,
     pub cargo_common_all: Vec<OsString>,
     pub cargo_common_test: Vec<OsString>,
     pub cargo_test_args: Vec<OsString>,
     pub harness_args: Vec<OsString>,
+    /// Arguments as given, grouped by flag along with its values, and their destinations.
+    pub classified_args: Vec<(Vec<OsString>, ArgDest)>,
 }
 
 impl Cli {
@@ -71,6 +175,75 @@ impl Cli {
         if self.shell && !self.exec.is_empty() {
             bail!("--shell and -x/--exec cannot be used at the same time");
         }
+        if self.shell_on_failure && self.pause_before_cleanup {
+            bail!("--shell-on-failure and --pause-before-cleanup cannot be used at the same time");
+        }
+        if self.record.is_some() && self.replay.is_some() {
+            bail!("--record and --replay cannot be used at the same time");
+        }
+        if self.replay.is_some() && !self.fixture_args.is_empty() {
+            bail!("-A/--arg cannot be used with --replay, the fixture program isn't run");
+        }
+        if self.no_fixture {
+            if self.record.is_some() || self.replay.is_some() {
+                bail!("--record and --replay cannot be used with --no-fixture");
+            }
+            if !self.fixture_args.is_empty() {
+                bail!("-A/--arg cannot be used with --no-fixture, there's no fixture program");
+            }
+        } else if self.kv_file.is_some() || self.env_file.is_some() {
+            bail!("--kv-file and --env-file can only be used with --no-fixture");
+        }
+        if self.coverage {
+            if !self.subcommand.runs_cargo() || self.shell || !self.exec.is_empty() || self.nextest
+            {
+                bail!(
+                    "--coverage can only be used when running cargo test, cargo bench or cargo run"
+                );
+            }
+            let target_dir = self.cargo_common_test.iter().any(|arg| {
+                arg.to_str()
+                    .is_some_and(|arg| arg == "--target-dir" || arg.starts_with("--target-dir="))
+            });
+            if target_dir {
+                bail!("--target-dir cannot be used with --coverage, instrumented code is built in target/coverage");
+            }
+            if self.coverage_fixture && (self.replay.is_some() || self.no_fixture) {
+                bail!("--coverage-fixture cannot be used without a fixture program");
+            }
+        } else if self.coverage_format.is_some() || self.coverage_fixture {
+            bail!("--coverage-format and --coverage-fixture can only be used with --coverage");
+        }
+        if self.subcommand != Subcommand::Env
+            && (self.env_format.is_some() || self.env_output.is_some())
+        {
+            bail!("--format and --output can only be used with the env subcommand");
+        }
+        if matches!(self.subcommand, Subcommand::Bench | Subcommand::Run) {
+            if self.nextest {
+                bail!(
+                    "--nextest cannot be used with the {} subcommand",
+                    self.subcommand.as_ref()
+                );
+            }
+            if self.shell || !self.exec.is_empty() {
+                bail!(
+                    "--shell and -x/--exec cannot be used with the {} subcommand",
+                    self.subcommand.as_ref()
+                );
+            }
+        }
+        let has_test_args = !(self.cargo_test_args.is_empty() && self.harness_args.is_empty());
+        if has_test_args && !self.subcommand.runs_cargo() {
+            bail!(
+                "Unexpected arguments for the {} subcommand: {:?}",
+                self.subcommand.as_ref(),
+                self.cargo_test_args
+                    .iter()
+                    .chain(self.harness_args.iter())
+                    .collect::<Vec<_>>()
+            );
+        }
 
         Ok(self)
     }
@@ -83,16 +256,35 @@ impl Cli {
 impl Default for Cli {
     fn default() -> Self {
         Self {
+            subcommand: Subcommand::default(),
             fixture_name: "fixture".to_string(),
             fixture_args: vec![],
             exec: vec![],
             shell: false,
             nextest: false,
             log_level: LogLevel::default(),
+            event_log: None,
+            timings_json: None,
+            test_conn_timeout: None,
+            fail_on_leaks: false,
+            shell_on_failure: false,
+            pause_before_cleanup: false,
+            dry_run: false,
+            env_format: None,
+            env_output: None,
+            record: None,
+            replay: None,
+            no_fixture: false,
+            kv_file: None,
+            env_file: None,
+            coverage: false,
+            coverage_format: None,
+            coverage_fixture: false,
             cargo_common_all: vec![],
             cargo_common_test: vec![],
             cargo_test_args: vec![],
             harness_args: vec![],
+            classified_args: vec![],
         }
     }
 }
@@ -2,12 +2,13 @@ use std::fmt;
 
 use crate::utils::StringExt;
 
-use super::parser::ParseFn;
+use super::{parser::ParseFn, ArgDest};
 
 pub struct FlagDef {
     pub short: Option<&'static str>,
     pub long: Option<&'static str>,
     pub parse_fn: &'static ParseFn,
+    pub dest: ArgDest,
     pub help: &'static str,
     pub meta: Option<&'static str>,
 }
@@ -17,6 +18,7 @@ impl FlagDef {
         short: None,
         long: None,
         parse_fn: &|_p| Ok(()),
+        dest: ArgDest::CargoFixture,
         help: "",
         meta: None,
     };
@@ -45,6 +47,7 @@ impl fmt::Debug for FlagDef {
             short,
             long,
             parse_fn,
+            dest,
             help,
             meta,
         } = self;
@@ -52,6 +55,7 @@ impl fmt::Debug for FlagDef {
             .field("short", &short)
             .field("long", &long)
             .field("parse_fn", &(parse_fn as *const _))
+            .field("dest", &dest)
             .field("help", &help)
             .field("meta", &meta)
             .finish()
@@ -75,6 +79,7 @@ macro_rules! def_flags {
                 $( short: Some(stringify!($short)), )?
                 $( long: Some(concat!(stringify!($long) $(, "-", stringify!($long2) $(, "-", stringify!($long3))?)?)), )?
                 parse_fn: def_flags!(@action $action $(($field))?),
+                dest: def_flags!(@dest $($field)?),
                 $( meta: Some(def_flags!(@meta $($meta)+)), )?
                 $( help: $help, )?
 
@@ -93,6 +98,7 @@ macro_rules! def_flags {
     // Actions
     (@action set_flag($field:ident)) => { &|parser| { parser.set_flag(|cli| { &mut cli.$field }) } };
     (@action parse_value($field:ident)) => { &|parser| { parser.parse_value(|cli| { &mut cli.$field }) } };
+    (@action parse_opt_value($field:ident)) => { &|parser| { parser.parse_opt_value(|cli| { &mut cli.$field }) } };
     (@action append_value_raw($field:ident)) => { &|parser| { parser.append_value_raw(|cli| { &mut cli.$field }) } };
     (@action forward($field:ident)) => { &|parser| { parser.forward(|cli| { &mut cli.$field }) } };
     (@action forward_value($field:ident)) => { &|parser| { parser.forward_value(|cli| { &mut cli.$field }) } };
@@ -101,6 +107,14 @@ macro_rules! def_flags {
     (@action help) => { &|parser| { parser.help() } };
     (@action version) => { &|parser| { parser.version() } };
 
+    // Where the flag ends up, as reported by --dry-run
+    (@dest fixture_args) => { $crate::cli::ArgDest::Fixture };
+    (@dest exec) => { $crate::cli::ArgDest::Exec };
+    (@dest cargo_common_all) => { $crate::cli::ArgDest::CargoAll };
+    (@dest cargo_common_test) => { $crate::cli::ArgDest::CargoTest };
+    (@dest cargo_test_args) => { $crate::cli::ArgDest::CargoTestArgs };
+    (@dest $($field:ident)?) => { $crate::cli::ArgDest::CargoFixture };
+
     // Parsing of meta args
     (@meta $meta:ident ...) => { concat!(stringify!($meta), "...") };
     (@meta $meta:ident=$meta2:ident) => { concat!(stringify!($meta), "=", stringify!($meta2)) };
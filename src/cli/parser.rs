@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, VecDeque},
     ffi::OsString,
-    mem,
+    iter, mem,
     str::FromStr,
 };
 
@@ -10,7 +10,9 @@ use os_str_bytes::RawOsStr;
 use tabular::{row, Table};
 use thiserror::Error;
 
-use super::{flags::FlagDef, Cli};
+use strum::{EnumMessage as _, IntoEnumIterator as _};
+
+use super::{flags::FlagDef, ArgDest, Cli, Subcommand};
 use crate::utils::OsStrExt as _;
 
 pub type ParseResult<T> = std::result::Result<T, Error>;
@@ -49,7 +51,7 @@ macro_rules! bail {
     };
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct RawFlag {
     short: bool,
     flag: String, // includes the -/-- prefix
@@ -220,6 +222,8 @@ pub struct Parser {
     current_flag: RawFlag,
     current_flag_def: &'static FlagDef,
     delimiter_found: bool,
+    /// Values taken by the current flag, for classification.
+    consumed: Vec<OsString>,
 
     // Result
     cli: Cli,
@@ -250,17 +254,22 @@ impl Parser {
             current_flag: RawFlag::empty(), // dummy value
             current_flag_def: &FlagDef::EMPTY,
             delimiter_found: false,
+            consumed: vec![],
             cli: Cli::default(),
         }
     }
 
     pub fn parse(mut self) -> ParseResult<Cli> {
+        self.parse_subcommand();
+
         while let Some(arg) = self.args.pop_front() {
             let flag = match arg {
                 NormalizedArg::Prog(_) => continue,
                 NormalizedArg::CargoExt(_) => continue,
                 NormalizedArg::Flag(flag) if self.delimiter_found => {
-                    self.cli.harness_args.push(flag.into());
+                    let flag = OsString::from(flag);
+                    self.classify([flag.clone()], ArgDest::Harness);
+                    self.cli.harness_args.push(flag);
                     continue;
                 }
                 NormalizedArg::Flag(flag) => flag,
@@ -269,8 +278,10 @@ impl Parser {
                 }
                 NormalizedArg::Positional(arg) => {
                     if !self.delimiter_found {
+                        self.classify([arg.clone()], ArgDest::CargoTestArgs);
                         self.cli.cargo_test_args.push(arg);
                     } else {
+                        self.classify([arg.clone()], ArgDest::Harness);
                         self.cli.harness_args.push(arg);
                     }
                     continue;
@@ -287,14 +298,19 @@ impl Parser {
                 &self.longs
             };
 
-            let Some(flag_def) = def_map.get(flag.name()) else {
-                self.cli.unknown_flag(flag.into());
+            let Some(&flag_def) = def_map.get(flag.name()) else {
+                let flag = OsString::from(flag);
+                self.classify([flag.clone()], ArgDest::CargoTestArgs);
+                self.cli.unknown_flag(flag);
                 continue;
             };
 
+            let raw_flag = OsString::from(flag.clone());
             self.current_flag = flag;
             self.current_flag_def = flag_def;
             (flag_def.parse_fn)(&mut self)?;
+            let consumed = mem::take(&mut self.consumed);
+            self.classify(iter::once(raw_flag).chain(consumed), flag_def.dest);
 
             if self.current_flag.eq_value.is_some() {
                 bail!(
@@ -307,6 +323,31 @@ impl Parser {
         Ok(self.cli)
     }
 
+    /// A subcommand is only recognized as the first argument.
+    fn parse_subcommand(&mut self) {
+        let first = self
+            .args
+            .iter()
+            .position(|arg| !matches!(arg, NormalizedArg::Prog(_) | NormalizedArg::CargoExt(_)));
+        let Some(first) = first else {
+            return;
+        };
+
+        if let NormalizedArg::Positional(arg) = &self.args[first] {
+            if let Some(subcommand) = arg.to_str().and_then(|arg| arg.parse().ok()) {
+                self.cli.subcommand = subcommand;
+                let arg = OsString::from(self.args.remove(first).unwrap());
+                self.classify([arg], ArgDest::Subcommand);
+            }
+        }
+    }
+
+    fn classify(&mut self, args: impl IntoIterator<Item = OsString>, dest: ArgDest) {
+        self.cli
+            .classified_args
+            .push((args.into_iter().collect(), dest));
+    }
+
     fn take_current_flag(&mut self) -> RawFlag {
         mem::replace(&mut self.current_flag, RawFlag::empty())
     }
@@ -329,13 +370,16 @@ impl Parser {
             return Ok(value);
         }
 
-        self.args
+        let value = self
+            .args
             .pop_front()
             .and_then(|arg| match arg {
                 NormalizedArg::Positional(arg) => Some(arg),
                 _ => None,
             })
-            .ok_or_else(|| self.missing_value_error())
+            .ok_or_else(|| self.missing_value_error())?;
+        self.consumed.push(value.clone());
+        Ok(value)
     }
 
     // flag parse fns
@@ -346,6 +390,27 @@ impl Parser {
     }
 
     pub fn parse_value<T>(&mut self, field: impl Fn(&mut Cli) -> &mut T) -> ParseResult<()>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+    {
+        *field(&mut self.cli) = self.parse_value_inner()?;
+        Ok(())
+    }
+
+    pub fn parse_opt_value<T>(
+        &mut self,
+        field: impl Fn(&mut Cli) -> &mut Option<T>,
+    ) -> ParseResult<()>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: std::error::Error + Send + Sync + 'static,
+    {
+        *field(&mut self.cli) = Some(self.parse_value_inner()?);
+        Ok(())
+    }
+
+    fn parse_value_inner<T>(&mut self) -> ParseResult<T>
     where
         T: FromStr,
         <T as FromStr>::Err: std::error::Error + Send + Sync + 'static,
@@ -368,8 +433,7 @@ impl Parser {
                 )
             })
             .map_err(Error::Parsing)?;
-        *field(&mut self.cli) = value;
-        Ok(())
+        Ok(value)
     }
 
     pub fn append_value_raw(
@@ -390,7 +454,9 @@ impl Parser {
         if self.args.is_empty() {
             Err(self.missing_value_error())
         } else {
-            field.extend(self.args.drain(..).map(OsString::from));
+            let remaining = self.args.drain(..).map(OsString::from).collect::<Vec<_>>();
+            field.extend(remaining.iter().cloned());
+            self.consumed.extend(remaining);
             Ok(())
         }
     }
@@ -430,7 +496,7 @@ impl Parser {
 
     pub fn usage() -> String {
         let name = env!("CARGO_PKG_NAME").replace('-', " ");
-        format!("{name} [options...] [cargo test opts/args...] [-- test binary args...]")
+        format!("{name} [command] [options...] [cargo test opts/args...] [-- test binary args...]")
     }
 
     fn build_help(&self) -> String {
@@ -442,10 +508,17 @@ Arguments:
   [cargo test opts/args...]   Arguments passed to cargo test.
   [test binary args...]       Arguments passed to the test binary via cargo test [...] -- args...
 
-Options:
+Commands:
 "#
         );
 
+        let table =
+            Table::new("  {:<}  {:<}").with_row(row!("(none)", "Run the fixture and cargo test"));
+        let table = Subcommand::iter()
+            .filter_map(|cmd| Some((cmd.as_ref().to_string(), cmd.get_message()?)))
+            .fold(table, |table, (cmd, help)| table.with_row(row!(cmd, help)));
+        help.push_str(&format!("{table}\nOptions:\n"));
+
         let table = Table::new("  {:<}  {:<}");
         let table = self.flags.iter().fold(table, |table, flag| {
             table.with_row(row!(flag.help_def(), flag.help))
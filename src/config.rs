@@ -10,14 +10,28 @@ mod cargo_meta;
 use anyhow::{anyhow, Result};
 use log::debug;
 
+use cargo_fixture::rpc_socket::EnvTarget;
+
 use self::cargo_meta::CargoMetadata;
-use crate::{cli::Cli, FIXTURE_FEATURE};
+use crate::{
+    cli::{Cli, Subcommand},
+    coverage::{self, Profiled},
+    env_vars::EnvVars,
+    FIXTURE_FEATURE,
+};
+
+/// Session sockets are named `.cargo-fixture-<pid>.sock` and placed in the target dir.
+pub const SOCKET_PREFIX: &str = ".cargo-fixture-";
+pub const SOCKET_SUFFIX: &str = ".sock";
 
 #[derive(Debug)]
 pub struct Config {
     pub cli: Cli,
     pub cargo_exe: PathBuf,
+    pub target_dir: PathBuf,
     pub socket_path: PathBuf,
+    /// Executables built with `--coverage`.
+    pub coverage_objects: coverage::Objects,
 }
 
 impl Config {
@@ -34,12 +48,14 @@ impl Config {
         let target_dir = metadata.target_dir().clone();
         debug!("target dir: {}", target_dir.display());
         let pid = process::id();
-        let socket_path = target_dir.join(format!(".cargo-fixture-{pid}.sock"));
+        let socket_path = target_dir.join(format!("{SOCKET_PREFIX}{pid}{SOCKET_SUFFIX}"));
 
         Ok(Self {
             cli,
             cargo_exe,
+            target_dir,
             socket_path,
+            coverage_objects: Default::default(),
         })
     }
 
@@ -54,6 +70,9 @@ impl Config {
             FIXTURE_FEATURE,
             "--message-format=json-render-diagnostics",
         ]);
+        if self.cli.coverage {
+            coverage::instrument(self, &mut cmd, Profiled::Cargo);
+        }
 
         cmd
     }
@@ -63,6 +82,9 @@ impl Config {
 
         cmd.args(&self.cli.fixture_args)
             .env("CARGO_FIXTURE_SOCKET", &self.socket_path);
+        if self.cli.coverage {
+            coverage::instrument(self, &mut cmd, Profiled::Fixture);
+        }
 
         #[cfg(unix)]
         {
@@ -75,17 +97,21 @@ impl Config {
         cmd
     }
 
+    /// Whether the test command is a `cargo test` (or `cargo nextest run`, `cargo bench`, `cargo run`) invocation,
+    /// as opposed to a custom command or shell.
+    pub fn runs_cargo_test(&self, replace_exec: &[String]) -> bool {
+        !self.cli.shell && self.cli.exec.is_empty() && replace_exec.is_empty()
+    }
+
     pub fn test_cmd(
         &self,
-        extra_test_args: Vec<String>,
-        extra_harness_args: Vec<String>,
-        replace_exec: Vec<String>,
+        extra_test_args: &[String],
+        extra_harness_args: &[String],
+        replace_exec: &[String],
+        env: &EnvVars,
     ) -> Result<Command> {
         let mut cmd = if self.cli.shell {
-            let sh = env::var_os("SHELL").ok_or_else(|| {
-                anyhow!("The environment variable $SHELL is not set, needed by --shell")
-            })?;
-            Command::new(sh)
+            Self::shell_cmd("--shell")?
         } else if let Some(exec) = self.cli.exec.first() {
             let mut cmd = Command::new(exec);
             cmd.args(&self.cli.exec[1..]);
@@ -95,26 +121,76 @@ impl Config {
             cmd.args(&replace_exec[1..]);
             cmd
         } else {
-            let mut cmd = Command::new(self.cargo_exe.clone());
-            // NB. --features is additive
-            if !self.cli.nextest {
-                cmd.args(["test", "--features", FIXTURE_FEATURE])
-            } else {
-                cmd.args(["nextest", "run", "--features", FIXTURE_FEATURE])
-            };
-
-            cmd.args(&self.cli.cargo_common_all)
-                .args(&self.cli.cargo_common_test)
-                .args(&self.cli.cargo_test_args)
-                .args(extra_test_args)
-                .arg("--")
+            let mut cmd = self.cargo_test_cmd(extra_test_args, false);
+            cmd.arg("--")
                 .args(&self.cli.harness_args)
                 .args(extra_harness_args);
             cmd
         };
 
+        // When tests are built in a separate step, only test run variables apply here,
+        // custom commands get everything as we can't tell what they do.
+        let target = if self.runs_cargo_test(replace_exec) && env.is_split() {
+            EnvTarget::Test
+        } else {
+            EnvTarget::All
+        };
+        env.apply(&mut cmd, target);
         cmd.env("CARGO_FIXTURE_SOCKET", &self.socket_path);
+        if self.cli.coverage {
+            coverage::instrument(self, &mut cmd, Profiled::Cargo);
+        }
 
         Ok(cmd)
     }
+
+    /// Shell to inspect the environment in after tests failed, used by `--shell-on-failure`.
+    pub fn failure_shell_cmd(&self, env: &EnvVars) -> Result<Command> {
+        let mut cmd = Self::shell_cmd("--shell-on-failure")?;
+        env.apply(&mut cmd, EnvTarget::All);
+        cmd.env("CARGO_FIXTURE_SOCKET", &self.socket_path);
+        Ok(cmd)
+    }
+
+    fn shell_cmd(flag: &str) -> Result<Command> {
+        let sh = env::var_os("SHELL").ok_or_else(|| {
+            anyhow!("The environment variable $SHELL is not set, needed by {flag}")
+        })?;
+        Ok(Command::new(sh))
+    }
+
+    /// Command to build tests without running them, used when the build and test run environments differ,
+    /// and with `--coverage`, where it outputs JSON messages to learn which executables were built.
+    pub fn test_build_cmd(&self, extra_test_args: &[String], env: &EnvVars) -> Command {
+        let mut cmd = self.cargo_test_cmd(extra_test_args, true);
+        // cargo run has no --no-run, cargo build is used instead
+        if self.cli.subcommand != Subcommand::Run {
+            cmd.arg("--no-run");
+        }
+        env.apply(&mut cmd, EnvTarget::Build);
+        if self.cli.coverage {
+            cmd.arg("--message-format=json-render-diagnostics");
+            coverage::instrument(self, &mut cmd, Profiled::Cargo);
+        }
+        cmd
+    }
+
+    fn cargo_test_cmd(&self, extra_test_args: &[String], build_only: bool) -> Command {
+        let mut cmd = Command::new(self.cargo_exe.clone());
+        match self.cli.subcommand {
+            Subcommand::Bench => cmd.arg("bench"),
+            Subcommand::Run if build_only => cmd.arg("build"),
+            Subcommand::Run => cmd.arg("run"),
+            _ if self.cli.nextest => cmd.args(["nextest", "run"]),
+            _ => cmd.arg("test"),
+        };
+        // NB. --features is additive
+        cmd.args(["--features", FIXTURE_FEATURE]);
+
+        cmd.args(&self.cli.cargo_common_all)
+            .args(&self.cli.cargo_common_test)
+            .args(&self.cli.cargo_test_args)
+            .args(extra_test_args);
+        cmd
+    }
 }
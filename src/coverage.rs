@@ -0,0 +1,341 @@
+//! The `--coverage` mode: the fixture and tests are built with `-C instrument-coverage`
+//! in a separate target directory, and the profiles written by test runs are merged into a report.
+//!
+//! Everything goes into `<target dir>/coverage`:
+//! - `target/`: the instrumented build, kept apart so that it doesn't invalidate regular builds,
+//! - `profraw/`: raw profiles, fixture ones are prefixed with `fixture-`,
+//! - `coverage.profdata`, `lcov.info` or `html/`: the merged profile and the report.
+
+use std::{
+    collections::BTreeSet,
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context as _, Result};
+use futures_util::TryStreamExt as _;
+use log::{debug, info};
+use smol::future;
+use strum::EnumString;
+
+use crate::{
+    cargo_message::{Artifact, Message},
+    config::Config,
+    utils::{CommandExt as _, ExitStatusExt as _},
+};
+
+const FIXTURE_PROFRAW_PREFIX: &str = "fixture-";
+
+/// Source files not to include in the report, i.e. dependencies and the standard library.
+const IGNORE_FILENAME_REGEX: &str =
+    r"([/\\]\.cargo[/\\](registry|git)[/\\]|[/\\]\.rustup[/\\]|^/rustc/)";
+
+#[derive(EnumString, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[strum(ascii_case_insensitive)]
+pub enum CoverageFormat {
+    /// `lcov.info`, understood by most coverage tools and IDE plugins.
+    #[default]
+    Lcov,
+    /// A browsable report in the `html/` directory.
+    Html,
+}
+
+/// Which process the profile written by an instrumented program belongs to.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Profiled {
+    /// cargo and whatever it runs, i.e. build scripts and tests.
+    Cargo,
+    Fixture,
+}
+
+/// Instrumented executables built in this session, the report covers these.
+///
+/// Their paths are taken from cargo's JSON messages, so that binaries left in the target directory
+/// by earlier builds don't end up in the report.
+#[derive(Default, Debug)]
+pub struct Objects(Mutex<BTreeSet<PathBuf>>);
+
+impl Objects {
+    pub fn add(&self, path: PathBuf) {
+        self.0.lock().unwrap().insert(path);
+    }
+
+    fn list(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn coverage_dir(config: &Config) -> PathBuf {
+    config.target_dir.join("coverage")
+}
+
+fn profraw_dir(config: &Config) -> PathBuf {
+    coverage_dir(config).join("profraw")
+}
+
+/// Set up a command to build instrumented code and/or write profiles.
+pub fn instrument(config: &Config, cmd: &mut Command, profiled: Profiled) {
+    let prefix = match profiled {
+        Profiled::Cargo => {
+            add_rustflags(cmd, "instrument-coverage");
+            cmd.env("CARGO_TARGET_DIR", coverage_dir(config).join("target"));
+            ""
+        }
+        Profiled::Fixture => FIXTURE_PROFRAW_PREFIX,
+    };
+
+    let profile_file = profraw_dir(config).join(format!("{prefix}%p-%m.profraw"));
+    cmd.env("LLVM_PROFILE_FILE", profile_file);
+}
+
+/// Append a `-C` codegen option to the flags cargo passes to rustc.
+///
+/// cargo takes the first of `CARGO_ENCODED_RUSTFLAGS`, `RUSTFLAGS` and the config's rustflags that's set
+/// and ignores the rest, so the option is appended to whichever is in effect for the command,
+/// a value set on the command (e.g. by the fixture) taking precedence over the inherited one.
+/// Without either env var, `CARGO_BUILD_RUSTFLAGS` is used, which cargo merges with `build.rustflags`
+/// from config files. Note that `target.<triple>.rustflags` in a config file override that.
+fn add_rustflags(cmd: &mut Command, codegen_opt: &str) {
+    let (name, sep, mut flags) = if let Some(flags) = cmd_env(cmd, "CARGO_ENCODED_RUSTFLAGS") {
+        ("CARGO_ENCODED_RUSTFLAGS", "\x1f", flags)
+    } else if let Some(flags) = cmd_env(cmd, "RUSTFLAGS") {
+        ("RUSTFLAGS", " ", flags)
+    } else {
+        let flags = cmd_env(cmd, "CARGO_BUILD_RUSTFLAGS").unwrap_or_default();
+        ("CARGO_BUILD_RUSTFLAGS", " ", flags)
+    };
+
+    for flag in ["-C", codegen_opt] {
+        if !flags.is_empty() {
+            flags.push(sep);
+        }
+        flags.push(flag);
+    }
+    cmd.env(name, flags);
+}
+
+/// The value of an env var as seen by the command: set or removed on the command, or inherited.
+fn cmd_env(cmd: &Command, name: &str) -> Option<OsString> {
+    match cmd.get_envs().find(|(key, _)| *key == name) {
+        Some((_, value)) => value.map(ToOwned::to_owned),
+        None => env::var_os(name),
+    }
+}
+
+/// Run a build command set up by `Config::test_build_cmd()`, which outputs cargo JSON messages,
+/// and record the executables it builds. The fixture program is only included with `--coverage-fixture`.
+pub async fn build(config: &Config, cmd: Command) -> io::Result<ExitStatus> {
+    let output = cmd
+        .into_smol(Stdio::inherit(), Stdio::piped(), Stdio::inherit())
+        .output()
+        .await?;
+
+    let executables = Message::parse_stream(&output.stdout[..])
+        .try_filter_map(|msg| {
+            let exe = match msg {
+                Message::CompilerArtifact(artifact)
+                    if config.cli.coverage_fixture || !is_fixture(config, &artifact) =>
+                {
+                    artifact.executable
+                }
+                _ => None,
+            };
+            future::ready(Ok(exe))
+        })
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{err:#}")))?;
+    for exe in executables {
+        debug!("instrumented executable: {}", exe.display());
+        config.coverage_objects.add(exe);
+    }
+
+    Ok(output.status)
+}
+
+fn is_fixture(config: &Config, artifact: &Artifact) -> bool {
+    artifact.target.name == config.cli.fixture_name && artifact.target.kind.contains("test")
+}
+
+/// Remove profiles left over from a previous run.
+pub fn prepare(config: &Config) -> Result<()> {
+    let dir = profraw_dir(config);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .with_context(|| format!("Could not remove coverage data in {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create coverage directory {}", dir.display()))?;
+    Ok(())
+}
+
+/// Merge the profiles and write the report.
+pub fn report(config: &Config) -> Result<()> {
+    let coverage_dir = coverage_dir(config);
+    let with_fixture = config.cli.coverage_fixture;
+
+    let profraws = list_files(&profraw_dir(config))?
+        .into_iter()
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.ends_with(".profraw")
+                && (with_fixture || !name.starts_with(FIXTURE_PROFRAW_PREFIX))
+        })
+        .collect::<Vec<_>>();
+    if profraws.is_empty() {
+        bail!("No coverage data found, were any tests run?");
+    }
+
+    let objects = config.coverage_objects.list();
+    let Some((first_object, objects)) = objects.split_first() else {
+        bail!("No instrumented binaries were built");
+    };
+
+    let profdata = coverage_dir.join("coverage.profdata");
+    let mut merge = Command::new(llvm_tool("llvm-profdata")?);
+    merge
+        .args(["merge", "-sparse", "-o"])
+        .arg(&profdata)
+        .args(&profraws);
+    run(merge, "llvm-profdata merge")?;
+
+    let format = config.cli.coverage_format.unwrap_or_default();
+    let mut cov = Command::new(llvm_tool("llvm-cov")?);
+    match format {
+        CoverageFormat::Lcov => cov.args(["export", "-format=lcov"]),
+        CoverageFormat::Html => cov.args(["show", "-format=html", "-show-line-counts-or-regions"]),
+    };
+    cov.arg("-instr-profile")
+        .arg(&profdata)
+        .arg(format!("-ignore-filename-regex={IGNORE_FILENAME_REGEX}"))
+        .arg(first_object);
+    for object in objects {
+        cov.arg("-object").arg(object);
+    }
+
+    let report_path = match format {
+        CoverageFormat::Lcov => {
+            let report_path = coverage_dir.join("lcov.info");
+            let output = File::create(&report_path).with_context(|| {
+                format!("Could not create coverage report {}", report_path.display())
+            })?;
+            cov.stdout(output);
+            report_path
+        }
+        CoverageFormat::Html => {
+            let report_path = coverage_dir.join("html");
+            cov.arg(format!("-output-dir={}", report_path.display()));
+            report_path.join("index.html")
+        }
+    };
+    run(cov, "llvm-cov")?;
+
+    info!("coverage report written to {}", report_path.display());
+    Ok(())
+}
+
+fn run(mut cmd: Command, name: &str) -> Result<()> {
+    debug!("running {}", cmd.display());
+    cmd.stdin(Stdio::null())
+        .status()
+        .with_context(|| format!("Could not run {name}"))?
+        .as_result(name)
+}
+
+/// LLVM tools need to match the LLVM version used by rustc, so only the ones
+/// installed with rustup's `llvm-tools` component are used.
+fn llvm_tool(name: &str) -> Result<PathBuf> {
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| OsString::from("rustc"));
+    let rustc_output = |args: &[&str]| -> Result<String> {
+        let output = Command::new(&rustc)
+            .args(args)
+            .output()
+            .context("Could not run rustc")?;
+        output.status.as_result("rustc")?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    let sysroot = rustc_output(&["--print", "sysroot"])?;
+    let version = rustc_output(&["-vV"])?;
+    let host = version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .context("Could not determine the host target from `rustc -vV`")?;
+
+    let tool = Path::new(&sysroot)
+        .join("lib/rustlib")
+        .join(host)
+        .join("bin")
+        .join(name)
+        .with_extension(env::consts::EXE_EXTENSION);
+    if !tool.exists() {
+        bail!("{name} not found, coverage requires the llvm-tools rustup component: rustup component add llvm-tools");
+    }
+    Ok(tool)
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Could not read {}", dir.display()))?;
+    let mut files = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, process::Command};
+
+    use super::add_rustflags;
+
+    fn env_of<'a>(cmd: &'a Command, name: &str) -> Option<&'a OsStr> {
+        cmd.get_envs()
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value)
+    }
+
+    #[test]
+    fn rustflags_appended() {
+        // A value set on the command, e.g. by the fixture
+        let mut cmd = Command::new("cargo");
+        cmd.env_remove("CARGO_ENCODED_RUSTFLAGS")
+            .env("RUSTFLAGS", "--cfg fixture");
+        add_rustflags(&mut cmd, "instrument-coverage");
+        assert_eq!(
+            env_of(&cmd, "RUSTFLAGS").unwrap(),
+            "--cfg fixture -C instrument-coverage"
+        );
+
+        // The encoded form takes precedence over RUSTFLAGS
+        let mut cmd = Command::new("cargo");
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", "--cfg\x1ffixture")
+            .env("RUSTFLAGS", "--cfg ignored");
+        add_rustflags(&mut cmd, "instrument-coverage");
+        assert_eq!(
+            env_of(&cmd, "CARGO_ENCODED_RUSTFLAGS").unwrap(),
+            "--cfg\x1ffixture\x1f-C\x1finstrument-coverage"
+        );
+        assert_eq!(env_of(&cmd, "RUSTFLAGS").unwrap(), "--cfg ignored");
+
+        // Without env flags, the config's build.rustflags are kept
+        let mut cmd = Command::new("cargo");
+        cmd.env_remove("CARGO_ENCODED_RUSTFLAGS")
+            .env_remove("RUSTFLAGS")
+            .env("CARGO_BUILD_RUSTFLAGS", "");
+        add_rustflags(&mut cmd, "instrument-coverage");
+        assert_eq!(
+            env_of(&cmd, "CARGO_BUILD_RUSTFLAGS").unwrap(),
+            "-C instrument-coverage"
+        );
+        assert!(env_of(&cmd, "RUSTFLAGS").is_none());
+    }
+}
@@ -0,0 +1,69 @@
+//! The `--dry-run` mode, prints what would be run instead of running it.
+
+use std::{fmt::Write as _, path::Path, process::Command};
+
+use anyhow::Result;
+use tabular::{row, Table};
+
+use cargo_fixture::rpc_socket::Request;
+
+use crate::{config::Config, env_vars::EnvVars, server::seed, utils::CommandExt as _};
+
+pub fn run(config: &Config) -> Result<()> {
+    let mut out = String::new();
+
+    out.push_str("Arguments:\n");
+    if config.cli.classified_args.is_empty() {
+        out.push_str("  (none)\n");
+    } else {
+        let table = config.cli.classified_args.iter().fold(
+            Table::new("  {:<}  {:<}"),
+            |table, (args, dest)| {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                table.with_row(row!(args, dest))
+            },
+        );
+        out.push_str(&table.to_string());
+    }
+
+    let mut section = |title: &str, cmd: &Command| {
+        let _ = writeln!(out, "{title}:\n  {}", cmd.display_with_env());
+    };
+    if config.cli.replay.is_none() && !config.cli.no_fixture {
+        section("Fixture build command", &config.fixture_build_cmd());
+        section(
+            "Fixture run command",
+            &config.fixture_run_cmd(Path::new("<fixture binary>")),
+        );
+    }
+    // With --no-fixture, the environment is known up front
+    let mut env = EnvVars::default();
+    if config.cli.no_fixture {
+        let requests = seed::requests(
+            config.cli.kv_file.as_deref(),
+            config.cli.env_file.as_deref(),
+        )?;
+        for req in requests {
+            if let Request::UpdateEnv { ops, target, .. } = req {
+                for op in ops {
+                    env.update(target, op)?;
+                }
+            }
+        }
+    }
+    if config.cli.coverage {
+        section("Test build command", &config.test_build_cmd(&[], &env));
+    }
+    let test_cmd = config.test_cmd(&[], &[], &[], &env)?;
+    section("Test command", &test_cmd);
+    if !config.cli.no_fixture {
+        out.push_str("Note: the fixture may still change the test command and its environment.\n");
+    }
+
+    print!("{out}");
+    Ok(())
+}
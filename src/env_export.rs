@@ -0,0 +1,127 @@
+//! The `cargo fixture env` subcommand output, the fixture environment in a format other tools can load.
+
+use std::{collections::BTreeMap, ffi::OsString, fmt::Write as _};
+
+use log::warn;
+use serde_json::{json, Value};
+use strum::EnumString;
+
+use crate::env_vars::is_portable_name;
+
+/// Variable name -> value, `None` meaning the variable is removed.
+pub type Vars = BTreeMap<String, Option<OsString>>;
+
+#[derive(EnumString, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[strum(ascii_case_insensitive)]
+pub enum EnvFormat {
+    /// Shell script to be sourced, `export NAME='value'`.
+    #[default]
+    Sh,
+    /// `.env` file, `NAME="value"`. Removed variables can't be expressed and are left out.
+    Dotenv,
+    /// JSON object with the `env` and `kv` fields.
+    Json,
+}
+
+pub fn export(format: EnvFormat, vars: &Vars, kv: &BTreeMap<String, Value>) -> String {
+    let value = |value: &OsString| value.to_string_lossy().into_owned();
+    let mut out = String::new();
+
+    // Names are written as they are in scripts, anything other than an identifier could inject code
+    let script_vars = || {
+        vars.iter().filter(|(name, _)| {
+            let portable = is_portable_name(name);
+            if !portable {
+                warn!(
+                    "env var `{}` left out of the export, its name is not a valid shell identifier",
+                    name.escape_debug()
+                );
+            }
+            portable
+        })
+    };
+
+    match format {
+        EnvFormat::Sh => {
+            for (name, val) in script_vars() {
+                let _ = match val {
+                    Some(val) => writeln!(out, "export {name}={}", sh_quote(&value(val))),
+                    None => writeln!(out, "unset {name}"),
+                };
+            }
+            kv_comments(&mut out, kv);
+        }
+        EnvFormat::Dotenv => {
+            for (name, val) in script_vars() {
+                if let Some(val) = val {
+                    let _ = writeln!(out, "{name}={}", dotenv_quote(&value(val)));
+                }
+            }
+            kv_comments(&mut out, kv);
+        }
+        EnvFormat::Json => {
+            let env = vars
+                .iter()
+                .map(|(name, val)| (name.clone(), json!(val.as_ref().map(value))))
+                .collect::<serde_json::Map<_, _>>();
+            let json = json!({ "env": env, "kv": kv });
+            let _ = writeln!(out, "{json:#}");
+        }
+    }
+
+    out
+}
+
+/// Tests get K-V data over the socket, it's listed for reference only.
+fn kv_comments(out: &mut String, kv: &BTreeMap<String, Value>) {
+    for (key, value) in kv {
+        // The value is JSON, but the key could contain a newline
+        let _ = writeln!(out, "# K-V: {} = {value}", key.escape_debug());
+    }
+}
+
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn dotenv_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+        .replace('$', r"\$");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::{export, EnvFormat, Vars};
+
+    #[test]
+    fn script_injection() {
+        let vars = Vars::from([
+            ("GOOD_1".to_string(), Some("'; rm -rf ~".into())),
+            ("A;rm -rf ~".to_string(), Some("x".into())),
+            ("$(cmd)".to_string(), None),
+            ("1ST".to_string(), Some("x".into())),
+        ]);
+        let kv = BTreeMap::from([("key\nrm -rf ~".to_string(), json!("value\n"))]);
+
+        let sh = export(EnvFormat::Sh, &vars, &kv);
+        assert_eq!(
+            sh,
+            "export GOOD_1=''\\''; rm -rf ~'\n# K-V: key\\nrm -rf ~ = \"value\\n\"\n"
+        );
+        let dotenv = export(EnvFormat::Dotenv, &vars, &kv);
+        assert!(dotenv.starts_with("GOOD_1=\"'; rm -rf ~\"\n#"), "{dotenv}");
+        assert_eq!(dotenv.lines().count(), 2, "{dotenv}");
+
+        // JSON can express any name
+        let json = export(EnvFormat::Json, &vars, &kv);
+        assert!(json.contains("A;rm -rf ~"), "{json}");
+    }
+}
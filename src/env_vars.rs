@@ -0,0 +1,201 @@
+//! Environment variables requested by the fixture.
+//!
+//! These are collected separately for the build and the test run of the test command
+//! and only ever applied to the commands we spawn, never to our own process environment.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsString,
+    iter,
+    path::PathBuf,
+    process::Command,
+};
+
+use anyhow::{Context as _, Result};
+
+use cargo_fixture::rpc_socket::{EnvOp, EnvTarget, EnvVarStatus, Request, REDACTED};
+
+/// Whether the name is a portable environment variable name, i.e. `[A-Za-z_][A-Za-z0-9_]*`,
+/// as accepted by shells.
+pub fn is_portable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Variable name -> value, `None` meaning the variable is to be removed.
+type EnvMap = BTreeMap<String, Option<OsString>>;
+
+#[derive(Default, Debug)]
+pub struct EnvVars {
+    build: EnvMap,
+    test: EnvMap,
+    /// Names of variables set as secrets, their values are redacted in reports.
+    secrets: BTreeSet<String>,
+}
+
+impl EnvVars {
+    pub fn update(&mut self, target: EnvTarget, op: EnvOp) -> Result<()> {
+        let maps = match target {
+            EnvTarget::All => vec![&mut self.build, &mut self.test],
+            EnvTarget::Build => vec![&mut self.build],
+            EnvTarget::Test => vec![&mut self.test],
+        };
+
+        for map in maps {
+            let (name, value) = Self::eval_op(map, op.clone())?;
+            map.insert(name, value);
+        }
+
+        Ok(())
+    }
+
+    /// Redact the variable's value in reports from now on, it stays a secret even if overwritten later.
+    pub fn mark_secret(&mut self, name: &str) {
+        self.secrets.insert(name.to_string());
+    }
+
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets.contains(name)
+    }
+
+    /// A copy of an env var request with the values of secret variables redacted, for logs,
+    /// or `None` if there's nothing to redact.
+    ///
+    /// Requests marked as secret are redacted as a whole when logged, this covers later requests
+    /// that don't have the flag, but update a variable that was set as a secret before.
+    pub fn redact_request(&self, req: &Request) -> Option<Request> {
+        match req {
+            Request::SetEnv { name, .. } if self.is_secret(name) => Some(Request::SetEnv {
+                name: name.clone(),
+                value: REDACTED.to_string(),
+            }),
+            Request::UpdateEnv {
+                ops,
+                target,
+                secret: false,
+            } if ops.iter().any(|op| self.is_secret(op.name())) => Some(Request::UpdateEnv {
+                ops: ops.iter().map(|op| self.redact_op(op)).collect(),
+                target: *target,
+                secret: false,
+            }),
+            _ => None,
+        }
+    }
+
+    fn redact_op(&self, op: &EnvOp) -> EnvOp {
+        if !self.is_secret(op.name()) {
+            return op.clone();
+        }
+        let name = op.name().to_string();
+        match op {
+            EnvOp::Set { .. } => EnvOp::Set {
+                name,
+                value: REDACTED.to_string(),
+            },
+            EnvOp::Unset { .. } => EnvOp::Unset { name },
+            EnvOp::PrependPath { .. } => EnvOp::PrependPath {
+                name,
+                path: REDACTED.to_string(),
+            },
+            EnvOp::AppendPath { .. } => EnvOp::AppendPath {
+                name,
+                path: REDACTED.to_string(),
+            },
+        }
+    }
+
+    /// The variables set so far, for inspection. Variables with the same value for the build and the test run are merged.
+    pub fn status(&self) -> Vec<EnvVarStatus> {
+        let status = |name: &String, value: &Option<OsString>, target| EnvVarStatus {
+            name: name.clone(),
+            value: value.as_ref().map(|v| {
+                if self.is_secret(name) {
+                    REDACTED.to_string()
+                } else {
+                    v.to_string_lossy().into_owned()
+                }
+            }),
+            target,
+        };
+
+        let mut res = vec![];
+        for (name, value) in &self.build {
+            if self.test.get(name) == Some(value) {
+                res.push(status(name, value, EnvTarget::All));
+            } else {
+                res.push(status(name, value, EnvTarget::Build));
+            }
+        }
+        for (name, value) in &self.test {
+            if self.build.get(name) != Some(value) {
+                res.push(status(name, value, EnvTarget::Test));
+            }
+        }
+        res.sort_by(|a, b| a.name.cmp(&b.name));
+        res
+    }
+
+    /// The variables as applied to commands with [`EnvTarget::All`].
+    pub fn merged(&self) -> EnvMap {
+        let mut merged = self.build.clone();
+        merged.extend(self.test.clone());
+        merged
+    }
+
+    /// Whether the build and the test run use a different environment.
+    pub fn is_split(&self) -> bool {
+        // NB. secrets are not compared, they only affect reports
+        self.build != self.test
+    }
+
+    pub fn apply(&self, cmd: &mut Command, target: EnvTarget) {
+        let vars: Box<dyn Iterator<Item = (&String, &Option<OsString>)>> = match target {
+            // Test run values take precedence, they are applied last
+            EnvTarget::All => Box::new(self.build.iter().chain(self.test.iter())),
+            EnvTarget::Build => Box::new(self.build.iter()),
+            EnvTarget::Test => Box::new(self.test.iter()),
+        };
+
+        for (name, value) in vars {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+    }
+
+    fn eval_op(map: &EnvMap, op: EnvOp) -> Result<(String, Option<OsString>)> {
+        // Current value as seen by the spawned command
+        let current = |name: &str| match map.get(name) {
+            Some(value) => value.clone(),
+            None => env::var_os(name),
+        };
+
+        let res = match op {
+            EnvOp::Set { name, value } => (name, Some(value.into())),
+            EnvOp::Unset { name } => (name, None),
+            EnvOp::PrependPath { name, path } => {
+                let existing = current(&name);
+                let existing = existing.iter().flat_map(env::split_paths);
+                let value = join_paths(&name, iter::once(PathBuf::from(path)).chain(existing))?;
+                (name, Some(value))
+            }
+            EnvOp::AppendPath { name, path } => {
+                let existing = current(&name);
+                let existing = existing.iter().flat_map(env::split_paths);
+                let value = join_paths(&name, existing.chain(iter::once(PathBuf::from(path))))?;
+                (name, Some(value))
+            }
+        };
+
+        Ok(res)
+    }
+}
+
+fn join_paths(name: &str, paths: impl Iterator<Item = PathBuf>) -> Result<OsString> {
+    env::join_paths(paths).with_context(|| format!("Could not join paths for env var {name}"))
+}
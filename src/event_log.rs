@@ -0,0 +1,141 @@
+//! Machine-readable session event log, enabled with `--event-log <path>`.
+//!
+//! Events are written as JSON Lines, one object per line. Every object has a `ts` field
+//! (milliseconds since the UNIX epoch) and an `event` field with the event name,
+//! the remaining fields depend on the event type, see [`Event`].
+//!
+//! The schema is versioned by the `schema` field of the `session-start` event,
+//! it is only bumped on incompatible changes; adding new events or fields is not considered incompatible.
+
+use std::{
+    fs::File,
+    io::{LineWriter, Write as _},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context as _, Result};
+use log::warn;
+use serde::{Serialize, Serializer};
+
+use cargo_fixture::rpc_socket::{redact_value, ConnectionType, Request};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+static EVENT_LOG: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    SessionStart {
+        schema: u32,
+        version: &'a str,
+    },
+    BuildStart {
+        command: String,
+    },
+    BuildEnd {
+        success: bool,
+        duration_ms: u64,
+    },
+    FixtureSpawn {
+        command: String,
+        pid: u32,
+    },
+    Handshake {
+        conn: u64,
+        connection_type: ConnectionType,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        name: Option<&'a str>,
+    },
+    Request {
+        conn: u64,
+        #[serde(serialize_with = "serialize_redacted")]
+        request: &'a Request,
+    },
+    Disconnect {
+        conn: u64,
+    },
+    TestTimeout {
+        conn: u64,
+        name: Option<&'a str>,
+        timeout_ms: u64,
+    },
+    LeakedConnection {
+        conn: u64,
+        name: Option<&'a str>,
+        connected_ms: u64,
+    },
+    TestCommand {
+        command: String,
+    },
+    TestsFinished {
+        success: bool,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
+    Cleanup {
+        duration_ms: u64,
+    },
+    SessionEnd {
+        exit_code: i32,
+        error: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    ts: u64,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Open the event log file, if requested. Without a call to `init()`, events are discarded.
+pub fn init(path: Option<&Path>) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    let file = File::create(path)
+        .with_context(|| format!("Could not create event log file {}", path.display()))?;
+    let _ = EVENT_LOG.set(Mutex::new(LineWriter::new(file)));
+
+    emit(Event::SessionStart {
+        schema: SCHEMA_VERSION,
+        version: env!("CARGO_PKG_VERSION"),
+    });
+    Ok(())
+}
+
+pub fn emit(event: Event<'_>) {
+    let Some(log) = EVENT_LOG.get() else {
+        return;
+    };
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(millis)
+        .unwrap_or(0);
+    let record = Record { ts, event };
+    let mut line = match serde_json::to_string(&record) {
+        Ok(line) => line,
+        Err(err) => return warn!("could not serialize event log record: {err}"),
+    };
+    line.push('\n');
+
+    if let Err(err) = log.lock().unwrap().write_all(line.as_bytes()) {
+        warn!("could not write to event log: {err}");
+    }
+}
+
+/// Secret values are never written to the log.
+fn serialize_redacted<S: Serializer>(request: &&Request, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut value = serde_json::to_value(request).map_err(serde::ser::Error::custom)?;
+    redact_value(&mut value);
+    value.serialize(serializer)
+}
+
+pub fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
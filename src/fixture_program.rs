@@ -12,18 +12,21 @@ use log::{debug, error, info, warn};
 use smol::{io, process::Child, stream::StreamExt, Task, Timer};
 
 use crate::{
+    cargo_message::Message,
     config::Config,
+    event_log::{self, Event},
     utils::{CommandExt, ExitStatusExt},
 };
 
-mod cargo_message;
-use cargo_message::Message;
-
 pub async fn build(config: &Config) -> Result<PathBuf> {
     info!("building fixture program...");
     let fixture_name = config.cli.fixture_name.clone();
     let cmd = config.fixture_build_cmd();
     debug!("running {}", cmd.display());
+    event_log::emit(Event::BuildStart {
+        command: cmd.display().to_string(),
+    });
+    let start = Instant::now();
 
     let mut cmd = cmd.into_smol(Stdio::null(), Stdio::piped(), Stdio::inherit());
     let mut child = cmd.spawn()?;
@@ -53,23 +56,43 @@ pub async fn build(config: &Config) -> Result<PathBuf> {
     });
 
     // Wait for cargo to exit
-    child.status().await?.as_result("cargo test")?;
+    let res = async {
+        child.status().await?.as_result("cargo test")?;
+
+        artifact_find
+            .await
+            .ok_or_else(|| anyhow!("fixture artifact not found in cargo JSON output"))?
+            .context("error reading cargo JSON output")
+    }
+    .await;
+
+    if let Ok(fixture_bin) = &res {
+        if config.cli.coverage_fixture {
+            config.coverage_objects.add(fixture_bin.clone());
+        }
+    }
 
-    artifact_find
-        .await
-        .ok_or_else(|| anyhow!("fixture artifact not found in cargo JSON output"))?
-        .context("error reading cargo JSON output")
+    event_log::emit(Event::BuildEnd {
+        success: res.is_ok(),
+        duration_ms: event_log::millis(start.elapsed()),
+    });
+    res
 }
 
 pub fn run(config: &Config, fixture_bin: &Path) -> Result<FixtureProcess> {
     info!("setting up fixture...");
     let cmd = config.fixture_run_cmd(fixture_bin);
-    debug!("running {}", cmd.display());
+    let cmd_display = cmd.display().to_string();
+    debug!("running {cmd_display}");
 
     let mut child = cmd
         .into_smol(Stdio::null(), Stdio::inherit(), Stdio::inherit())
         .spawn()
         .with_context(|| "error running fixture program".to_string())?;
+    event_log::emit(Event::FixtureSpawn {
+        command: cmd_display,
+        pid: child.id(),
+    });
 
     let err_context = "fixture program failed".to_string();
     let status_ft = child
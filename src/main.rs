@@ -1,42 +1,99 @@
 #![doc = include_str!("../README.md")]
 
-use std::{env, process::ExitCode, sync::Arc};
+use std::{env, process::ExitCode, sync::Arc, time::Instant};
 
 use anyhow::{bail, Context, Result};
 use fixture_program::FixtureProcess;
 use futures_util::{future::FusedFuture as _, pin_mut, select, FutureExt};
+use log::info;
 use server::Server;
+use timings::{Phase, Timings};
 
 use crate::{
+    cli::Subcommand,
     config::Config,
+    event_log::Event,
     utils::{ctrlc_2x, ResultExt},
 };
 
+mod cargo_message;
 mod cli;
 mod config;
+mod coverage;
+mod dry_run;
+mod env_export;
+mod env_vars;
+mod event_log;
 mod fixture_program;
 mod logger;
 mod server;
+mod status;
+mod timings;
 mod utils;
 
 const FIXTURE_FEATURE: &str = "_fixture"; // kept in sync with the `with_fixture` macro
 const ENV_CARGO_FIXTURE: &str = "CARGO_FIXTURE";
 
 fn main() -> Result<ExitCode> {
+    let cli = cli::parse()?;
+    logger::init(cli.log_level);
+
+    // Inspecting a session is fine from within it, e.g. from a --shell
+    if cli.subcommand == Subcommand::Status {
+        let config = Config::new(cli)?;
+        status::run(&config)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.dry_run {
+        let config = Config::new(cli)?;
+        dry_run::run(&config)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
     if env::var_os(ENV_CARGO_FIXTURE).is_some() {
         bail!("Cannot run cargo fixture inside another cargo fixture");
     }
     env::set_var(ENV_CARGO_FIXTURE, "1");
 
-    let cli = cli::parse()?;
-    logger::init(cli.log_level);
-    let config = Config::new(cli)?;
+    event_log::init(cli.event_log.as_deref())?;
+    let config = Arc::new(Config::new(cli)?);
+
+    if config.cli.coverage {
+        coverage::prepare(&config)?;
+    }
+
+    let timings = Arc::new(Timings::default());
+    let res = smol::block_on(async {
+        if config.cli.replay.is_some() || config.cli.no_fixture {
+            serve_fixtureless(config.clone(), timings.clone()).await
+        } else {
+            serve(config.clone(), timings.clone()).await
+        }
+    });
+    // The report is written even when tests fail, as long as they ran
+    let res = res.and_then(|code| {
+        if config.cli.coverage {
+            coverage::report(&config)?;
+        }
+        Ok(code)
+    });
+    event_log::emit(Event::SessionEnd {
+        exit_code: *res.as_ref().unwrap_or(&1),
+        error: res.as_ref().err().map(|err| format!("{err:#}")),
+    });
+
+    let report = timings.report();
+    info!("timings:\n{report}");
+    if let Some(path) = config.cli.timings_json.as_deref() {
+        report.write_json(path)?;
+    }
 
-    let status = smol::block_on(serve(config))?;
+    let status = res?;
     Ok(ExitCode::from(status as u8))
 }
 
-async fn serve(config: Config) -> Result<i32> {
+async fn serve(config: Arc<Config>, timings: Arc<Timings>) -> Result<i32> {
     // SIGINT handling:
     // The fixture process is set to use a new process group, ie. it doesn't receive SIGINTs.
     // The cargo test/-x process is created in the default (ours) group and gets SIGINT as usual,
@@ -46,15 +103,15 @@ async fn serve(config: Config) -> Result<i32> {
     // For this purpose this ctrlc_2x future is created:
     let mut ctrlc_2x = ctrlc_2x()?;
 
-    let config = Arc::new(config);
-
     // Build fixture program
+    timings.enter(Phase::Build);
     let fixture_bin = fixture_program::build(&config)
         .await
         .context("Could not build fixture program")?;
 
     // Create a UDS server
-    let server = Server::new(config.clone())?;
+    timings.enter(Phase::Setup);
+    let server = Server::new(config.clone(), timings.clone())?;
 
     // Run fixture program and accept its connection
     let fixture_ps = fixture_program::run(&config, &fixture_bin)?;
@@ -76,6 +133,7 @@ async fn serve(config: Config) -> Result<i32> {
     // Handle fixture connection and accept + handle test connections
     // the fixture connection handler runs cargo test
     let mut fixture_conn = smol::spawn(fixture_conn.run()).fuse();
+    let live_conns = server.live_conns();
     let server = smol::spawn(server.accept_tests()); // NB .detach() does't run Drops
 
     // Wait for fixture connection and process to wrap up
@@ -83,26 +141,57 @@ async fn serve(config: Config) -> Result<i32> {
         select! {
             res = fixture_ps => res.log_error(),
             res = fixture_conn => break res,
-            _ = ctrlc_2x => fixture_ps.kill(),
+            _ = ctrlc_2x => {
+                live_conns.log("Double Ctrl+C received");
+                fixture_ps.kill();
+            },
         }
     };
 
-    if fixture_ps.is_terminated() {
-        return test_res;
+    let cleanup_start = Instant::now();
+    let res = if fixture_ps.is_terminated() {
+        test_res
+    } else {
+        FixtureProcess::busy_logger("wrapped up").detach();
+        loop {
+            select! {
+                res = fixture_ps => {
+                    break if let Ok(0) = test_res {
+                        res.map(|_| 0)
+                    } else {
+                        test_res
+                    };
+                },
+                _ = ctrlc_2x => fixture_ps.kill(),
+            }
+        }
     };
 
-    FixtureProcess::busy_logger("wrapped up").detach();
-    loop {
-        select! {
-            res = fixture_ps => {
-                server.cancel().await; // https://github.com/smol-rs/smol/issues/294
-                return if let Ok(0) = test_res {
-                    res.map(|_| 0)
-                } else {
-                    test_res
-                };
-            },
-            _ = ctrlc_2x => fixture_ps.kill(),
-        }
-    }
+    server.cancel().await; // https://github.com/smol-rs/smol/issues/294
+    event_log::emit(Event::Cleanup {
+        duration_ms: event_log::millis(cleanup_start.elapsed()),
+    });
+    res
+}
+
+/// Like `serve()`, but there's no fixture process, the fixture connection is fed
+/// from a recording (`--replay`) or from the `--kv-file` and `--env-file` contents (`--no-fixture`).
+async fn serve_fixtureless(config: Arc<Config>, timings: Arc<Timings>) -> Result<i32> {
+    // SIGINTs are only handled by the test command, see serve()
+    let _ctrlc = ctrlc_2x()?;
+
+    timings.enter(Phase::Setup);
+    let server = Server::new(config.clone(), timings)?;
+    let fixture_conn = match config.cli.replay.as_deref() {
+        Some(path) => server.replay_fixture(path)?,
+        None => server.seed_fixture(
+            config.cli.kv_file.as_deref(),
+            config.cli.env_file.as_deref(),
+        )?,
+    };
+    let server = smol::spawn(server.accept_tests());
+
+    let res = fixture_conn.run().await;
+    server.cancel().await;
+    res
 }
@@ -1,114 +1,273 @@
 use std::{
-    collections::HashMap,
-    env, mem,
-    process::Stdio,
-    sync::{Arc, Mutex, RwLock},
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Write as _},
+    mem,
+    path::Path,
+    process::{self, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::{bail, Context, Result};
-use log::{debug, info, trace, warn};
-use smol::Task;
+use log::{debug, error, info, log, trace, warn, Level};
+use smol::{
+    future::{self, FutureExt as _},
+    Task, Timer,
+};
 
-use cargo_fixture::rpc_socket::{ConnectionType, Request, Response, RpcSocket};
+use cargo_fixture::rpc_socket::{
+    ConnectionType, EnvOp, EnvTarget, EnvVarStatus, Envelope, ErrorCode, PhaseStatus, Request,
+    Response, SessionStatus, REDACTED,
+};
 
-use crate::{config::Config, utils::CommandExt as _};
+use crate::{
+    cli::Subcommand,
+    config::Config,
+    coverage, env_export,
+    env_vars::EnvVars,
+    event_log::{self, Event},
+    timings::{Phase, Timings},
+    utils::{ctrlc_once, suspend_ctrlc_2x, CommandExt as _, ResultExt as _},
+};
 
+mod live_conns;
+mod recording;
+mod scheduler;
+pub mod seed;
 mod server_socket;
-use server_socket::ServerSocket;
+pub use live_conns::LiveConns;
+use live_conns::LiveGuard;
+use recording::{FixtureSource, Recorder};
+use scheduler::Scheduler;
+use server_socket::{Connection, Incoming, ServerSocket, Socket};
+
+type KvStore = Arc<RwLock<HashMap<String, KvEntry>>>;
+
+#[derive(Debug)]
+struct KvEntry {
+    value: serde_json::Value,
+    /// Set using `set_secret_value()`, the value is redacted in logs and reports.
+    secret: bool,
+}
 
-type KvStore = Arc<RwLock<HashMap<String, serde_json::Value>>>;
+impl KvEntry {
+    fn redacted(&self) -> serde_json::Value {
+        if self.secret {
+            REDACTED.into()
+        } else {
+            self.value.clone()
+        }
+    }
+}
+
+/// Connection ID of a replayed or seeded fixture connection, the same as a live one gets, as it's accepted first.
+const REPLAY_CONN_ID: u64 = 0;
+
+/// How long to wait for test connections to close once the test command exits before reporting them as leaked.
+const LEAK_GRACE_PERIOD: Duration = Duration::from_millis(500);
 
 pub struct Server {
-    config: Arc<Config>,
     socket: ServerSocket,
+    shared: Shared,
+}
+
+/// State shared by the connection handlers.
+#[derive(Clone)]
+struct Shared {
+    config: Arc<Config>,
+    timings: Arc<Timings>,
     kv_store: KvStore,
-    test_conns: Mutex<Vec<Task<()>>>,
+    scheduler: Arc<Scheduler>,
+    live_conns: Arc<LiveConns>,
+    /// Env vars set by the fixture so far, for inspection.
+    env_status: Arc<Mutex<Vec<EnvVarStatus>>>,
+    /// Set when a test connection exceeds its time limit, fails the run.
+    timed_out: Arc<AtomicBool>,
 }
 
 impl Server {
-    pub fn new(config: Arc<Config>) -> Result<Self> {
+    pub fn new(config: Arc<Config>, timings: Arc<Timings>) -> Result<Self> {
         let socket = ServerSocket::new(&config.socket_path)?;
         Ok(Self {
-            config,
             socket,
-            kv_store: KvStore::default(),
-            test_conns: Default::default(),
+            shared: Shared {
+                config,
+                timings,
+                kv_store: KvStore::default(),
+                scheduler: Default::default(),
+                live_conns: Default::default(),
+                env_status: Default::default(),
+                timed_out: Default::default(),
+            },
         })
     }
 
     pub async fn accept_fixture(&self) -> Result<FixtureConnection> {
-        let (socket, conn_type) = self
-            .socket
-            .accept()
-            .await
-            .context("Fixture connection error")?;
-        if conn_type != ConnectionType::Fixture {
-            bail!("Unexpected connection {conn_type:?}, expected fixture connection first");
+        loop {
+            let mut conn = self
+                .socket
+                .accept()
+                .await?
+                .handshake()
+                .await
+                .context("Fixture connection error")?;
+            match conn.conn_type {
+                ConnectionType::Fixture => {
+                    conn.confirm().await.context("Fixture connection error")?;
+                    let source = FixtureSource::Live(conn.socket);
+                    return Ok(FixtureConnection::new(source, conn.id, &self.shared));
+                }
+                // The session can be inspected during fixture setup as well
+                ConnectionType::Inspector => {
+                    smol::spawn(self.shared.clone().handle_connection(conn)).detach()
+                }
+                other => {
+                    let msg = format!(
+                        "Unexpected connection {other:?}, expected fixture connection first"
+                    );
+                    return conn
+                        .reject(ErrorCode::UnexpectedRequest, msg)
+                        .await
+                        .context("Fixture connection error");
+                }
+            }
         }
+    }
 
-        Ok(FixtureConnection::new(
-            socket,
-            self.config.clone(),
-            self.kv_store.clone(),
-        ))
+    /// Feed the requests recorded with `--record` to the fixture connection handler
+    /// instead of a live fixture connection.
+    pub fn replay_fixture(&self, path: &Path) -> Result<FixtureConnection> {
+        let source = FixtureSource::replay(path)?;
+        Ok(FixtureConnection::new(source, REPLAY_CONN_ID, &self.shared))
+    }
+
+    /// Feed the fixture connection handler the contents of `--kv-file` and `--env-file`
+    /// when running with `--no-fixture`.
+    pub fn seed_fixture(
+        &self,
+        kv_file: Option<&Path>,
+        env_file: Option<&Path>,
+    ) -> Result<FixtureConnection> {
+        let source = FixtureSource::Replay(seed::requests(kv_file, env_file)?);
+        Ok(FixtureConnection::new(source, REPLAY_CONN_ID, &self.shared))
+    }
+
+    /// Test connections currently open, shared with the server task.
+    pub fn live_conns(&self) -> Arc<LiveConns> {
+        self.shared.live_conns.clone()
     }
 
     pub async fn accept_tests(self) -> Result<()> {
+        // Each connection is handled in its own task, so that neither a slow handshake
+        // nor a test waiting to be scheduled holds up accepting other connections.
+        // The tasks are cancelled when this future is dropped.
+        let mut test_conns: Vec<Task<()>> = vec![];
         loop {
-            let conn = self.socket.accept().await?;
-            self.handle_test_connection(conn).await?;
+            let incoming = match self.socket.accept().await {
+                Ok(incoming) => incoming,
+                Err(err) => {
+                    warn!("{err:#}");
+                    continue;
+                }
+            };
+
+            test_conns.retain(|task| !task.is_finished());
+            let task = smol::spawn(self.shared.clone().handle_incoming(incoming));
+            test_conns.push(task);
         }
     }
+}
 
-    pub async fn handle_test_connection(
-        &self,
-        (socket, conn_type): (RpcSocket, ConnectionType),
-    ) -> Result<()> {
-        let serial = match conn_type {
+impl Shared {
+    async fn handle_incoming(self, incoming: Incoming) {
+        match incoming.handshake().await {
+            Ok(conn) => self.handle_connection(conn).await,
+            // The client was told the reason, keep serving others
+            Err(err) => warn!("Rejected test connection: {err:#}"),
+        }
+    }
+
+    async fn handle_connection(self, mut conn: Connection) {
+        let serial = match conn.conn_type {
             ConnectionType::Client => false,
             ConnectionType::ClientSerial => true,
+            ConnectionType::Inspector => {
+                // Not scheduled, inspection doesn't interfere with tests
+                if let Err(err) = conn.confirm().await {
+                    return warn!("Inspector connection error: {err:#}");
+                }
+                return InspectorConnection::new(conn, self).run().await;
+            }
             ConnectionType::Fixture => {
-                bail!("Unexpected connection {conn_type:?}, expected test connection")
+                let msg = "Unexpected fixture connection, expected test connection".to_string();
+                if let Err(err) = conn.reject::<()>(ErrorCode::UnexpectedRequest, msg).await {
+                    warn!("Rejected test connection: {err:#}");
+                }
+                return;
             }
         };
 
-        if !serial {
-            let test = TestConnection::new(socket, self.kv_store.clone());
-            let task = smol::spawn(test.run());
-            self.test_conns.lock().unwrap().push(task);
-        } else {
-            // 1. wait for all outstanding test tasks to finish
-            let test_conns = mem::take(&mut *self.test_conns.lock().unwrap());
-            for task in test_conns {
-                task.await;
-            }
-
-            // 2. run the serial test and wait for it to finish
-            TestConnection::new(socket, self.kv_store.clone())
-                .run()
-                .await;
+        // The handshake is only completed once the test is scheduled to run, the client waits until then
+        let _permit = self
+            .scheduler
+            .acquire(conn.id, conn.name.clone(), serial)
+            .await;
+        if let Err(err) = conn.confirm().await {
+            return warn!("Test connection error: {err:#}");
         }
+        self.test_connection(conn, serial).run().await;
+    }
 
-        Ok(())
+    fn test_connection(&self, conn: Connection, serial: bool) -> TestConnection {
+        // The test's own time limit takes precedence over the CLI one
+        let timeout = conn
+            .timeout
+            .or(self.config.cli.test_conn_timeout.map(|timeout| timeout.0));
+        TestConnection {
+            _live: self.live_conns.register(conn.id, conn.name.clone(), serial),
+            socket: conn.socket,
+            id: conn.id,
+            name: conn.name,
+            timeout,
+            timings: self.timings.clone(),
+            kv_store: self.kv_store.clone(),
+            timed_out: self.timed_out.clone(),
+        }
     }
 }
 
 /// Handles connection from the fixture process, spawns `cargo test` as part of this.
 pub struct FixtureConnection {
-    socket: RpcSocket,
+    source: FixtureSource,
+    id: u64,
     config: Arc<Config>,
+    timings: Arc<Timings>,
     kv_store: KvStore,
+    timed_out: Arc<AtomicBool>,
+    live_conns: Arc<LiveConns>,
+    env_status: Arc<Mutex<Vec<EnvVarStatus>>>,
+    env: EnvVars,
     extra_test_args: Vec<String>,
     extra_harness_args: Vec<String>,
     replace_exec: Vec<String>,
 }
 
 impl FixtureConnection {
-    fn new(socket: RpcSocket, config: Arc<Config>, kv_store: KvStore) -> Self {
+    fn new(source: FixtureSource, id: u64, shared: &Shared) -> Self {
         Self {
-            socket,
-            config,
-            kv_store,
+            source,
+            id,
+            config: shared.config.clone(),
+            timings: shared.timings.clone(),
+            kv_store: shared.kv_store.clone(),
+            timed_out: shared.timed_out.clone(),
+            live_conns: shared.live_conns.clone(),
+            env_status: shared.env_status.clone(),
+            env: EnvVars::default(),
             extra_test_args: vec![],
             extra_harness_args: vec![],
             replace_exec: vec![],
@@ -116,43 +275,96 @@ impl FixtureConnection {
     }
 
     pub async fn run(mut self) -> Result<i32> {
+        let mut recorder = match self.config.cli.record.as_deref() {
+            Some(path) => Some(Recorder::create(path, &self.config.cli.fixture_name)?),
+            None => None,
+        };
+
         loop {
-            let Some(req) = self.socket.recv().await? else {
+            let Some(Envelope { id, msg: req }) = self.source.recv_request().await? else {
+                event_log::emit(Event::Disconnect { conn: self.id });
                 bail!("fixture program never called .ready(), tests not run");
             };
+            let redacted = self.env.redact_request(&req);
+            event_log::emit(Event::Request {
+                conn: self.id,
+                request: redacted.as_ref().unwrap_or(&req),
+            });
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&req)?;
+            }
 
             let resp = match req {
                 Request::SetEnv { name, value } => self.handle_set_env(name, value),
-                Request::SetKeyValue { key, value } => self.handle_set_key_value(key, value),
+                Request::UpdateEnv {
+                    ops,
+                    target,
+                    secret,
+                } => self.handle_update_env(ops, target, secret),
+                Request::SetKeyValue { key, value, secret } => {
+                    self.handle_set_key_value(key, value, secret)
+                }
                 Request::GetKeyValue { key } => self.handle_get_key_value(key),
                 Request::SetExtraTestArgs { args } => self.handle_set_extra_test_args(args),
                 Request::SetExtraHarnessArgs { args } => self.handle_set_extra_harness_args(args),
                 Request::SetExec { exec } => self.handle_set_exec(exec),
 
-                Request::Ready => return self.run_tests().await,
-
-                hello @ Request::Hello { .. } => bail!("Unexpected Hello message: {hello:?}"),
+                Request::Ready if self.config.cli.subcommand == Subcommand::Env => {
+                    return self.export_env(id).await
+                }
+                Request::Ready => return self.run_tests(id).await,
+
+                Request::Hello { .. } => {
+                    warn!("Unexpected Hello message from fixture");
+                    Response::error(ErrorCode::UnexpectedRequest, "Unexpected Hello message")
+                }
+                Request::GetStatus => Response::error(
+                    ErrorCode::UnexpectedRequest,
+                    "GetStatus request is not allowed from the fixture",
+                ),
             };
 
-            self.socket.send(resp).await?;
+            self.source.respond(id, resp).await?;
         }
     }
 
-    fn handle_set_env(&self, name: String, value: String) -> Response {
-        debug!("setting env var {name}={value}");
-        env::set_var(name, value);
+    fn handle_set_env(&mut self, name: String, value: String) -> Response {
+        self.handle_update_env(vec![EnvOp::Set { name, value }], EnvTarget::All, false)
+    }
+
+    fn handle_update_env(&mut self, ops: Vec<EnvOp>, target: EnvTarget, secret: bool) -> Response {
+        for op in ops {
+            // A variable stays a secret when updated without the flag later
+            if secret || self.env.is_secret(op.name()) {
+                debug!("secret env var update for {target:?}: {}", op.name());
+                self.env.mark_secret(op.name());
+            } else {
+                debug!("env var update for {target:?}: {op:?}");
+            }
+            if let Err(err) = self.env.update(target, op) {
+                warn!("{err:#}");
+            }
+        }
+        *self.env_status.lock().unwrap() = self.env.status();
         Response::Ok
     }
 
-    fn handle_set_key_value(&mut self, key: String, value: serde_json::Value) -> Response {
+    fn handle_set_key_value(
+        &mut self,
+        key: String,
+        value: serde_json::Value,
+        secret: bool,
+    ) -> Response {
         debug!("storing KV data for key `{key}`");
-        self.kv_store.write().unwrap().insert(key, value);
+        self.kv_store
+            .write()
+            .unwrap()
+            .insert(key, KvEntry { value, secret });
         Response::Ok
     }
 
     fn handle_get_key_value(&mut self, key: String) -> Response {
-        let value = self.kv_store.read().unwrap().get(&key).cloned();
-        Response::KeyValue { key, value }
+        get_key_value(&self.kv_store, key)
     }
 
     fn handle_set_extra_test_args(&mut self, args: Vec<String>) -> Response {
@@ -179,64 +391,402 @@ impl FixtureConnection {
         Response::Ok
     }
 
-    async fn run_tests(mut self) -> Result<i32> {
-        trace!("KV storage: {:?}", *self.kv_store.read().unwrap());
+    /// Report test connections left open after the test command exited,
+    /// returns whether this should fail the run.
+    async fn check_leaked_conns(&self) -> bool {
+        let leaked = self.live_conns.wait_closed(LEAK_GRACE_PERIOD).await;
+        if leaked.is_empty() {
+            return false;
+        }
+
+        let fail = self.config.cli.fail_on_leaks;
+        let level = if fail { Level::Error } else { Level::Warn };
+        log!(
+            level,
+            "{} test connection(s) still open after the test command exited:",
+            leaked.len()
+        );
+        for conn in &leaked {
+            log!(level, "  {conn}");
+            event_log::emit(Event::LeakedConnection {
+                conn: conn.id,
+                name: conn.name.as_deref(),
+                connected_ms: event_log::millis(conn.start.elapsed()),
+            });
+        }
+        if fail {
+            error!("Failing the test run because of leaked test connections (--fail-on-leaks)");
+        }
+        fail
+    }
+
+    /// Keeps the fixture environment around for inspection after a failed test run,
+    /// as requested by `--shell-on-failure` or `--pause-before-cleanup`.
+    async fn pause_on_failure(&self, env: &EnvVars) -> Result<()> {
+        let cli = &self.config.cli;
+        if cli.shell_on_failure {
+            let shell = self.config.failure_shell_cmd(env)?;
+            self.timings.enter(Phase::Paused);
+            warn!(
+                "tests failed, running {} with the fixture environment, exit it to let the fixture clean up",
+                shell.display()
+            );
+            // Ctrl+Cs typed in the shell must not kill the fixture
+            let _ctrlc_suspended = suspend_ctrlc_2x();
+            let status = shell
+                .into_smol(Stdio::inherit(), Stdio::inherit(), Stdio::inherit())
+                .status()
+                .await?;
+            debug!("failure shell: {status:?}");
+        } else if cli.pause_before_cleanup {
+            self.timings.enter(Phase::Paused);
+            let ctrlc = ctrlc_once()?;
+            warn!("tests failed, press Enter or Ctrl+C to let the fixture clean up");
+            let enter = smol::unblock(|| io::stdin().read_line(&mut String::new()));
+            let ctrlc = async {
+                ctrlc.await;
+                Ok(())
+            };
+            async { enter.await.map(drop) }.or(ctrlc).await?;
+        }
+        Ok(())
+    }
+
+    /// `cargo fixture env`: instead of running tests, export the environment and keep the fixture
+    /// around for tests run by other tools until Ctrl+C.
+    async fn export_env(self, ready_id: Option<u64>) -> Result<i32> {
+        let cli = &self.config.cli;
+        let mut vars = self.env.merged();
+        vars.insert(
+            "CARGO_FIXTURE_SOCKET".to_string(),
+            Some(self.config.socket_path.clone().into()),
+        );
+        let kv = redacted_kv(&self.kv_store);
+        let output = env_export::export(cli.env_format.unwrap_or_default(), &vars, &kv);
+        let ctrlc = ctrlc_once()?;
+
+        self.timings.enter(Phase::Tests);
+        match cli.env_output.as_deref() {
+            Some(path) => {
+                fs::write(path, output)
+                    .with_context(|| format!("Could not write {}", path.display()))?;
+                info!("fixture environment written to {}", path.display());
+            }
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(output.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+
+        info!("fixture is ready, press Ctrl+C to let it clean up");
+        ctrlc.or(self.reject_requests()).await;
+
+        self.timings.enter(Phase::Cleanup);
+        let resp = Response::TestsFinished { success: true };
+        self.source.respond(ready_id, resp).await?;
+        Ok(0)
+    }
+
+    async fn run_tests(mut self, ready_id: Option<u64>) -> Result<i32> {
+        trace!("KV storage: {:?}", redacted_kv(&self.kv_store));
 
         let extra_test_args = mem::take(&mut self.extra_test_args);
         let extra_harness_args = mem::take(&mut self.extra_harness_args);
         let replace_exec = mem::take(&mut self.replace_exec);
-        let test_cmd = self
-            .config
-            .test_cmd(extra_test_args, extra_harness_args, replace_exec)?;
-        info!("running {}", test_cmd.display());
-        let status = test_cmd
-            .into_smol(Stdio::inherit(), Stdio::inherit(), Stdio::inherit())
-            .status()
+        let env = mem::take(&mut self.env);
+        let test_cmd =
+            self.config
+                .test_cmd(&extra_test_args, &extra_harness_args, &replace_exec, &env)?;
+
+        self.timings.enter(Phase::Tests);
+        let start = Instant::now();
+        let (status, success, failed) = self
+            .run_test_cmd(test_cmd, &extra_test_args, &replace_exec, &env)
+            .or(self.reject_requests())
             .await;
-        debug!("test command: {status:?}");
 
-        let success = status.as_ref().map(|s| s.success()).unwrap_or(false);
+        self.timings.enter(Phase::Cleanup);
+        event_log::emit(Event::TestsFinished {
+            success,
+            exit_code: status.as_ref().ok().and_then(|s| s.code()),
+            duration_ms: event_log::millis(start.elapsed()),
+        });
         let resp = Response::TestsFinished { success };
-        self.socket.send(resp).await?;
+        self.source.respond(ready_id, resp).await?;
 
         status
-            .map(|s| s.code().unwrap_or(1))
+            .map(|s| match s.code() {
+                Some(0) if failed => 1,
+                code => code.unwrap_or(1),
+            })
             .context("test command error")
     }
+
+    /// Run the test command, returns its status, overall success and whether the run failed for other reasons.
+    async fn run_test_cmd(
+        &self,
+        test_cmd: Command,
+        extra_test_args: &[String],
+        replace_exec: &[String],
+        env: &EnvVars,
+    ) -> (io::Result<ExitStatus>, bool, bool) {
+        // Build tests separately when build-only or test-only env vars are set,
+        // with --coverage to find out which executables to report on
+        let separate_build = env.is_split() || self.config.cli.coverage;
+        let build_status = if separate_build && self.config.runs_cargo_test(replace_exec) {
+            let build_cmd = self.config.test_build_cmd(extra_test_args, env);
+            info!("building tests: {}", build_cmd.display());
+            let status = if self.config.cli.coverage {
+                coverage::build(&self.config, build_cmd).await
+            } else {
+                build_cmd
+                    .into_smol(Stdio::inherit(), Stdio::inherit(), Stdio::inherit())
+                    .status()
+                    .await
+            };
+            debug!("test build command: {status:?}");
+            Some(status)
+        } else {
+            None
+        };
+
+        let status = match build_status {
+            Some(Ok(status)) if !status.success() => Ok(status),
+            Some(Err(err)) => Err(err),
+            _ => {
+                info!("running {}", test_cmd.display());
+                event_log::emit(Event::TestCommand {
+                    command: test_cmd.display().to_string(),
+                });
+                let hang_logger = self.live_conns.clone().hang_logger();
+                let status = test_cmd
+                    .into_smol(Stdio::inherit(), Stdio::inherit(), Stdio::inherit())
+                    .status()
+                    .await;
+                hang_logger.cancel().await;
+                debug!("test command: {status:?}");
+                status
+            }
+        };
+
+        let timed_out = self.timed_out.load(Ordering::Relaxed);
+        if timed_out {
+            error!("Some tests exceeded their time limit, see above, failing the test run");
+        }
+        let leaked = self.check_leaked_conns().await;
+        let failed = timed_out || leaked;
+        let success = status.as_ref().map(|s| s.success()).unwrap_or(false) && !failed;
+        if !success {
+            self.pause_on_failure(env).await.log_error();
+        }
+        (status, success, failed)
+    }
+
+    /// Serve requests the fixture sends while its `ready()` call is pending, ie. while the tests run,
+    /// so that concurrent calls don't hang. K-V values can be read, other requests are rejected.
+    ///
+    /// Never returns, meant to be raced against the test run.
+    async fn reject_requests<T>(&self) -> T {
+        if let FixtureSource::Live(socket) = &self.source {
+            loop {
+                let (id, req) = match socket.recv_request().await {
+                    Ok(Some(Envelope { id: Some(id), msg })) => (id, msg),
+                    // Without an ID the response would be taken for that of `ready()`
+                    Ok(Some(Envelope { id: None, msg })) => {
+                        warn!("Ignoring {} request sent during ready()", msg.as_ref());
+                        continue;
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        warn!("Fixture connection error: {err:#}");
+                        break;
+                    }
+                };
+                event_log::emit(Event::Request {
+                    conn: self.id,
+                    request: &req,
+                });
+
+                let resp = match req {
+                    Request::GetKeyValue { key } => get_key_value(&self.kv_store, key),
+                    other => {
+                        let msg = format!(
+                            "{} request is not allowed while tests are running",
+                            other.as_ref()
+                        );
+                        warn!("Fixture connection error: {msg}");
+                        Response::error(ErrorCode::UnexpectedRequest, msg)
+                    }
+                };
+                if let Err(err) = socket.respond(Some(id), resp).await {
+                    warn!("Fixture connection error: {err:#}");
+                    break;
+                }
+            }
+        }
+        future::pending().await
+    }
 }
 
 /// Handles connection from individual tests.
 struct TestConnection {
-    socket: RpcSocket,
+    _live: LiveGuard,
+    socket: Socket,
+    id: u64,
+    name: Option<String>,
+    timeout: Option<Duration>,
+    timings: Arc<Timings>,
     kv_store: KvStore,
+    timed_out: Arc<AtomicBool>,
 }
 
 impl TestConnection {
-    fn new(socket: RpcSocket, kv_store: KvStore) -> Self {
-        Self { socket, kv_store }
-    }
-
     async fn run(mut self) {
-        if let Err(err) = self.run_inner().await {
-            warn!("Test connection error: {err}");
+        let timings = self.timings.clone();
+        let _conn_guard = timings.test_conn();
+
+        let res = match self.timeout {
+            Some(timeout) => {
+                let timer = async {
+                    Timer::after(timeout).await;
+                    None
+                };
+                smol::future::or(async { Some(self.run_inner().await) }, timer).await
+            }
+            None => Some(self.run_inner().await),
+        };
+
+        match res {
+            Some(Ok(())) => {}
+            Some(Err(err)) => warn!("Test connection error: {err}"),
+            // Dropping the connection releases the serial lock, if any
+            None => self.handle_timeout(),
         }
     }
 
+    fn handle_timeout(&self) {
+        let timeout = self.timeout.unwrap_or_default();
+        error!(
+            "Test `{}` (connection {}) exceeded its time limit of {timeout:?}, dropping its connection",
+            self.name.as_deref().unwrap_or("<unnamed>"),
+            self.id,
+        );
+        event_log::emit(Event::TestTimeout {
+            conn: self.id,
+            name: self.name.as_deref(),
+            timeout_ms: event_log::millis(timeout),
+        });
+        self.timed_out.store(true, Ordering::Relaxed);
+    }
+
     async fn run_inner(&mut self) -> Result<()> {
         loop {
-            let Some(req) = self.socket.recv().await? else {
+            let Some(Envelope { id, msg: req }) = self.socket.recv_request().await? else {
+                event_log::emit(Event::Disconnect { conn: self.id });
                 return Ok(());
             };
+            event_log::emit(Event::Request {
+                conn: self.id,
+                request: &req,
+            });
             let resp = match req {
                 Request::GetKeyValue { key } => self.handle_get_key_value(key),
-                other => bail!("Unexpected message: {other:?}"),
+                other => {
+                    let msg = format!("{} request is not allowed from tests", other.as_ref());
+                    warn!("Test connection error: {msg}");
+                    Response::error(ErrorCode::UnexpectedRequest, msg)
+                }
             };
-            self.socket.send(resp).await?;
+            self.socket.respond(id, resp).await?;
         }
     }
 
     fn handle_get_key_value(&mut self, key: String) -> Response {
-        let value = self.kv_store.read().unwrap().get(&key).cloned();
-        Response::KeyValue { key, value }
+        get_key_value(&self.kv_store, key)
+    }
+}
+
+fn get_key_value(kv_store: &KvStore, key: String) -> Response {
+    let kv_store = kv_store.read().unwrap();
+    let entry = kv_store.get(&key);
+    Response::KeyValue {
+        value: entry.map(|entry| entry.value.clone()),
+        secret: entry.is_some_and(|entry| entry.secret),
+        key,
+    }
+}
+
+/// The K-V store contents with secret values redacted, for logs and reports.
+fn redacted_kv(kv_store: &KvStore) -> BTreeMap<String, serde_json::Value> {
+    kv_store
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(key, entry)| (key.clone(), entry.redacted()))
+        .collect()
+}
+
+/// Handles connection from `cargo fixture status`.
+struct InspectorConnection {
+    socket: Socket,
+    id: u64,
+    shared: Shared,
+}
+
+impl InspectorConnection {
+    fn new(conn: Connection, shared: Shared) -> Self {
+        Self {
+            socket: conn.socket,
+            id: conn.id,
+            shared,
+        }
+    }
+
+    async fn run(self) {
+        if let Err(err) = self.run_inner().await {
+            warn!("Inspector connection error: {err}");
+        }
+    }
+
+    async fn run_inner(&self) -> Result<()> {
+        loop {
+            let Some(Envelope { id, msg: req }) = self.socket.recv_request().await? else {
+                event_log::emit(Event::Disconnect { conn: self.id });
+                return Ok(());
+            };
+            let resp = match req {
+                Request::GetStatus => Response::Status(Box::new(self.status())),
+                other => {
+                    let msg = format!("{} request is not allowed from inspector", other.as_ref());
+                    Response::error(ErrorCode::UnexpectedRequest, msg)
+                }
+            };
+            self.socket.respond(id, resp).await?;
+        }
+    }
+
+    fn status(&self) -> SessionStatus {
+        let shared = &self.shared;
+        let phases = shared
+            .timings
+            .report()
+            .phases
+            .into_iter()
+            .map(|phase| PhaseStatus {
+                phase: phase.phase.as_ref().to_string(),
+                duration_ms: phase.duration_ms,
+            })
+            .collect();
+
+        SessionStatus {
+            pid: process::id(),
+            fixture: shared.config.cli.fixture_name.clone(),
+            env: shared.env_status.lock().unwrap().clone(),
+            kv: redacted_kv(&shared.kv_store),
+            tests: shared.live_conns.status(),
+            queue: shared.scheduler.queue_status(),
+            phases,
+        }
     }
 }
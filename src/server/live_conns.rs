@@ -0,0 +1,129 @@
+//! Tracking of open test connections, used to report tests that never disconnected.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt as _;
+use log::warn;
+use smol::{Task, Timer};
+
+use cargo_fixture::rpc_socket::TestStatus;
+
+use crate::event_log;
+
+const HANG_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct LiveConn {
+    pub id: u64,
+    pub name: Option<String>,
+    pub serial: bool,
+    pub start: Instant,
+}
+
+impl fmt::Display for LiveConn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` (connection {}{}), connected for {:.1?}",
+            self.name.as_deref().unwrap_or("<unnamed>"),
+            self.id,
+            if self.serial { ", serial" } else { "" },
+            self.start.elapsed(),
+        )
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct LiveConns {
+    conns: Mutex<BTreeMap<u64, LiveConn>>,
+}
+
+impl LiveConns {
+    /// Track a connection for as long as the returned guard is held.
+    pub fn register(self: &Arc<Self>, id: u64, name: Option<String>, serial: bool) -> LiveGuard {
+        let conn = LiveConn {
+            id,
+            name,
+            serial,
+            start: Instant::now(),
+        };
+        self.conns.lock().unwrap().insert(id, conn);
+        LiveGuard {
+            conns: self.clone(),
+            id,
+        }
+    }
+
+    /// Currently open connections, ordered by ID, i.e. from the oldest.
+    pub fn snapshot(&self) -> Vec<LiveConn> {
+        self.conns.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn status(&self) -> Vec<TestStatus> {
+        self.snapshot()
+            .into_iter()
+            .map(|conn| TestStatus {
+                duration_ms: event_log::millis(conn.start.elapsed()),
+                conn: conn.id,
+                name: conn.name,
+                serial: conn.serial,
+            })
+            .collect()
+    }
+
+    /// Log the open connections, if any, along with the reason for doing so.
+    pub fn log(&self, reason: &str) {
+        let conns = self.snapshot();
+        if conns.is_empty() {
+            return;
+        }
+
+        warn!("{reason}, tests still connected:");
+        for conn in conns {
+            warn!("  {conn}");
+        }
+    }
+
+    /// Periodically log the open connections while the test command runs, to help pinpoint hanging tests.
+    pub fn hang_logger(self: Arc<Self>) -> Task<()> {
+        smol::spawn(async move {
+            let start = Instant::now();
+            let mut timer = Timer::interval(HANG_LOG_INTERVAL);
+            while timer.next().await.is_some() {
+                let delta = start.elapsed().as_secs();
+                self.log(&format!("test command still running after {delta}s"));
+            }
+        })
+    }
+
+    /// Give connections closed by the test command some time to be noticed,
+    /// returns the ones still open after that.
+    pub async fn wait_closed(&self, grace: Duration) -> Vec<LiveConn> {
+        let start = Instant::now();
+        loop {
+            let conns = self.snapshot();
+            if conns.is_empty() || start.elapsed() >= grace {
+                return conns;
+            }
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+/// Removes the connection from `LiveConns` when dropped.
+#[derive(Debug)]
+pub struct LiveGuard {
+    conns: Arc<LiveConns>,
+    id: u64,
+}
+
+impl Drop for LiveGuard {
+    fn drop(&mut self) {
+        self.conns.conns.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,181 @@
+//! Recording of the fixture's requests with `--record` and their replay with `--replay`,
+//! which runs tests with the recorded environment and K-V data without any fixture process.
+//!
+//! A recording is a JSON Lines file, a header line followed by the recorded requests
+//! in the protocol's format, the last of which is `Ready`.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{LineWriter, Write as _},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use cargo_fixture::rpc_socket::{Envelope, Request, Response};
+
+use super::server_socket::Socket;
+
+const RECORDING_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Header {
+    recording: u32,
+    fixture: String,
+}
+
+/// Whether a request from the fixture is part of a recording.
+fn is_recorded(req: &Request) -> bool {
+    matches!(
+        req,
+        Request::SetEnv { .. }
+            | Request::UpdateEnv { .. }
+            | Request::SetKeyValue { .. }
+            | Request::SetExtraTestArgs { .. }
+            | Request::SetExtraHarnessArgs { .. }
+            | Request::SetExec { .. }
+            | Request::Ready
+    )
+}
+
+/// Whether a request carries a secret value, see `FixtureClient::set_secret_value()`.
+fn is_secret(req: &Request) -> bool {
+    matches!(
+        req,
+        Request::UpdateEnv { secret: true, .. } | Request::SetKeyValue { secret: true, .. }
+    )
+}
+
+/// Writes a recording. Secret values need to be replayed, so they are recorded as they are,
+/// on Unix the file is only readable by its owner, and a warning is logged.
+#[derive(Debug)]
+pub struct Recorder {
+    file: LineWriter<File>,
+    path: PathBuf,
+    warned_secret: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, fixture: &str) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Could not create recording file {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            // Set explicitly rather than on open, so that it also applies to an existing file
+            use std::os::unix::fs::PermissionsExt as _;
+            file.set_permissions(fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Could not set permissions of {}", path.display()))?;
+        }
+        let mut this = Self {
+            file: LineWriter::new(file),
+            path: path.to_path_buf(),
+            warned_secret: false,
+        };
+        this.write(&Header {
+            recording: RECORDING_VERSION,
+            fixture: fixture.to_string(),
+        })?;
+        Ok(this)
+    }
+
+    pub fn record(&mut self, req: &Request) -> Result<()> {
+        if !is_recorded(req) {
+            return Ok(());
+        }
+        if is_secret(req) && !self.warned_secret {
+            warn!(
+                "Secret values are saved unredacted in the recording {}, keep it private",
+                self.path.display()
+            );
+            self.warned_secret = true;
+        }
+        self.write(req)
+    }
+
+    fn write(&mut self, value: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_string(value)?;
+        line.push('\n');
+        self.file
+            .write_all(line.as_bytes())
+            .context("Could not write to recording file")
+    }
+}
+
+/// Where the requests handled by `FixtureConnection` come from.
+#[allow(clippy::large_enum_variant)] // There's only ever one
+#[derive(Debug)]
+pub enum FixtureSource {
+    Live(Socket),
+    Replay(VecDeque<Request>),
+}
+
+impl FixtureSource {
+    /// Read a recording made with `--record`.
+    pub fn replay(path: &Path) -> Result<Self> {
+        let recording = fs::read_to_string(path)
+            .with_context(|| format!("Could not read recording file {}", path.display()))?;
+        let mut lines = recording.lines();
+
+        let header = lines.next().unwrap_or_default();
+        let header: Header = serde_json::from_str(header)
+            .with_context(|| format!("{} is not a cargo fixture recording", path.display()))?;
+        if header.recording != RECORDING_VERSION {
+            bail!(
+                "Recording {} has unsupported version {}",
+                path.display(),
+                header.recording
+            );
+        }
+
+        // The header is line 1. NB. the lines aren't included in errors, they may contain secrets
+        let requests = lines
+            .zip(2..)
+            .map(|(line, num)| {
+                let req: Request = serde_json::from_str(line)
+                    .with_context(|| format!("Invalid request on line {num}"))?;
+                if !is_recorded(&req) {
+                    bail!("Unexpected {} request on line {num}", req.as_ref());
+                }
+                Ok(req)
+            })
+            .collect::<Result<VecDeque<_>>>()
+            .with_context(|| format!("Could not parse recording {}", path.display()))?;
+        if !matches!(requests.back(), Some(Request::Ready)) {
+            bail!(
+                "Recording {} is incomplete, the fixture never called ready()",
+                path.display()
+            );
+        }
+
+        info!(
+            "replaying fixture `{}` from {}",
+            header.fixture,
+            path.display()
+        );
+        Ok(Self::Replay(requests))
+    }
+
+    pub async fn recv_request(&mut self) -> Result<Option<Envelope<Request>>> {
+        match self {
+            Self::Live(socket) => Ok(socket.recv_request().await?),
+            Self::Replay(requests) => {
+                Ok(requests.pop_front().map(|msg| Envelope { id: None, msg }))
+            }
+        }
+    }
+
+    pub async fn respond(&self, id: Option<u64>, resp: Response) -> Result<()> {
+        match self {
+            Self::Live(socket) => Ok(socket.respond(id, resp).await?),
+            Self::Replay(_) => {
+                if let Response::Error { message, .. } = resp {
+                    warn!("Replayed request failed: {message}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Scheduling of test connections.
+//!
+//! Parallel tests may run alongside each other, a serial test runs alone.
+//! Tests are admitted in the order they arrived: once a serial test is waiting,
+//! parallel tests arriving after it wait as well, so that serial tests can't be starved
+//! by a steady stream of parallel ones (and vice versa).
+
+use std::{collections::VecDeque, sync::Mutex, time::Instant};
+
+use log::debug;
+use smol::channel::{self, Receiver, Sender};
+
+use cargo_fixture::rpc_socket::TestStatus;
+
+use crate::event_log;
+
+#[derive(Default, Debug)]
+pub struct Scheduler {
+    state: Mutex<State>,
+}
+
+#[derive(Default, Debug)]
+struct State {
+    parallel_running: usize,
+    serial_running: bool,
+    queue: VecDeque<Waiting>,
+}
+
+#[derive(Debug)]
+struct Waiting {
+    conn: u64,
+    name: Option<String>,
+    serial: bool,
+    since: Instant,
+    admit: Sender<()>,
+}
+
+impl State {
+    fn can_run(&self, serial: bool) -> bool {
+        if serial {
+            !self.serial_running && self.parallel_running == 0
+        } else {
+            !self.serial_running
+        }
+    }
+
+    fn start(&mut self, serial: bool) {
+        if serial {
+            self.serial_running = true;
+        } else {
+            self.parallel_running += 1;
+        }
+    }
+
+    fn finish(&mut self, serial: bool) {
+        if serial {
+            self.serial_running = false;
+        } else {
+            self.parallel_running -= 1;
+        }
+    }
+
+    /// Admit waiting tests from the front of the queue as long as they can run.
+    fn admit_waiting(&mut self) {
+        while let Some(next) = self.queue.front() {
+            if !self.can_run(next.serial) {
+                break;
+            }
+
+            // The receiver is alive for as long as the test is queued, see `Queued`
+            let next = self.queue.pop_front().unwrap();
+            let _ = next.admit.try_send(());
+            debug!("test connection {} admitted", next.conn);
+            self.start(next.serial);
+        }
+    }
+}
+
+impl Scheduler {
+    /// Wait until the test connection may run. The test is running for as long as the returned `Permit` is held.
+    pub async fn acquire(&self, conn: u64, name: Option<String>, serial: bool) -> Permit<'_> {
+        let queued = {
+            let mut state = self.state.lock().unwrap();
+            if state.queue.is_empty() && state.can_run(serial) {
+                state.start(serial);
+                return Permit {
+                    scheduler: self,
+                    serial,
+                };
+            }
+
+            debug!("test connection {conn} queued (serial: {serial})");
+            let (admit, admitted) = channel::bounded(1);
+            state.queue.push_back(Waiting {
+                conn,
+                name,
+                serial,
+                since: Instant::now(),
+                admit,
+            });
+            Queued {
+                scheduler: self,
+                conn,
+                serial,
+                admitted,
+                done: false,
+            }
+        };
+
+        queued.wait().await
+    }
+
+    /// Tests waiting to be let in, in order.
+    pub fn queue_status(&self) -> Vec<TestStatus> {
+        let state = self.state.lock().unwrap();
+        state
+            .queue
+            .iter()
+            .map(|waiting| TestStatus {
+                conn: waiting.conn,
+                name: waiting.name.clone(),
+                serial: waiting.serial,
+                duration_ms: event_log::millis(waiting.since.elapsed()),
+            })
+            .collect()
+    }
+}
+
+/// A test waiting in the queue. If the waiting is cancelled, e.g. because the test timed out
+/// or disconnected, dropping this removes the test from the queue.
+struct Queued<'a> {
+    scheduler: &'a Scheduler,
+    conn: u64,
+    serial: bool,
+    admitted: Receiver<()>,
+    /// Set once the test has been admitted and a `Permit` handed out.
+    done: bool,
+}
+
+impl<'a> Queued<'a> {
+    async fn wait(mut self) -> Permit<'a> {
+        // The sender is only dropped once it's been sent on
+        let _ = self.admitted.recv().await;
+        self.done = true;
+        Permit {
+            scheduler: self.scheduler,
+            serial: self.serial,
+        }
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let mut state = self.scheduler.state.lock().unwrap();
+        match state
+            .queue
+            .iter()
+            .position(|waiting| waiting.conn == self.conn)
+        {
+            Some(pos) => {
+                debug!("test connection {} left the queue", self.conn);
+                state.queue.remove(pos);
+            }
+            // Admitted in the meantime, but the permit was never picked up
+            None => state.finish(self.serial),
+        }
+        // A test that's been blocking the queue may have left it
+        state.admit_waiting();
+    }
+}
+
+/// Held by a running test, lets others in when dropped.
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+    serial: bool,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.finish(self.serial);
+        state.admit_waiting();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smol::future::{block_on, poll_once};
+
+    use super::Scheduler;
+
+    #[test]
+    fn cancel_while_queued() {
+        let scheduler = Scheduler::default();
+        block_on(async {
+            let parallel = scheduler.acquire(0, None, false).await;
+
+            // A serial test waits for the parallel one, then is cancelled
+            let mut serial = Box::pin(scheduler.acquire(1, None, true));
+            assert!(poll_once(&mut serial).await.is_none());
+            assert_eq!(scheduler.queue_status().len(), 1);
+            drop(serial);
+            assert!(scheduler.queue_status().is_empty());
+
+            // Parallel tests are no longer held back by it
+            let other = poll_once(scheduler.acquire(2, None, false)).await;
+            assert!(other.is_some());
+            drop(other);
+            drop(parallel);
+
+            // Nothing is running, so a serial test gets in right away
+            let serial = poll_once(scheduler.acquire(3, None, true)).await;
+            assert!(serial.is_some());
+        });
+    }
+
+    #[test]
+    fn cancel_once_admitted() {
+        let scheduler = Scheduler::default();
+        block_on(async {
+            let serial = scheduler.acquire(0, None, true).await;
+            let mut parallel = Box::pin(scheduler.acquire(1, None, false));
+            assert!(poll_once(&mut parallel).await.is_none());
+
+            // Admitted, but cancelled before picking up the permit
+            drop(serial);
+            assert!(scheduler.queue_status().is_empty());
+            drop(parallel);
+
+            let serial = poll_once(scheduler.acquire(2, None, true)).await;
+            assert!(serial.is_some());
+        });
+    }
+}
@@ -0,0 +1,139 @@
+//! The `--no-fixture` mode, the fixture connection is fed requests made up from
+//! the `--kv-file` and `--env-file` contents, for running tests against an environment set up by other means.
+
+use std::{collections::VecDeque, fs, path::Path};
+
+use anyhow::{bail, Context as _, Result};
+use serde_json::Value;
+
+use cargo_fixture::rpc_socket::{EnvOp, EnvTarget, Request};
+
+use crate::env_vars::is_portable_name;
+
+/// Requests setting up the K-V store and environment, ending with `Ready`.
+pub fn requests(kv_file: Option<&Path>, env_file: Option<&Path>) -> Result<VecDeque<Request>> {
+    let mut requests = VecDeque::new();
+
+    if let Some(path) = env_file {
+        let ops = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| parse_env_file(path, &contents))
+            .with_context(|| format!("Could not load env file {}", path.display()))?;
+        requests.push_back(Request::UpdateEnv {
+            ops,
+            target: EnvTarget::All,
+            secret: false,
+        });
+    }
+
+    if let Some(path) = kv_file {
+        let kv = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+            .with_context(|| format!("Could not load K-V file {}", path.display()))?;
+        let Value::Object(kv) = kv else {
+            bail!("K-V file {} must contain a JSON object", path.display());
+        };
+        requests.extend(kv.into_iter().map(|(key, value)| Request::SetKeyValue {
+            key,
+            value,
+            secret: false,
+        }));
+    }
+
+    requests.push_back(Request::Ready);
+    Ok(requests)
+}
+
+/// Parse a `.env` file, i.e. `NAME=value` lines, with optional `export` prefixes, `#` comments,
+/// and single- or double-quoted values, the latter supporting backslash escapes.
+fn parse_env_file(path: &Path, contents: &str) -> Result<Vec<EnvOp>> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(lineno, line)| {
+            let location = format!("{}:{lineno}", path.display());
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("{location}: expected NAME=value");
+            };
+            let name = name.trim();
+            if !is_portable_name(name) {
+                bail!(
+                    "{location}: invalid variable name `{}`, expected letters, digits and underscores",
+                    name.escape_debug()
+                );
+            }
+            let value = parse_env_value(value.trim()).with_context(|| location.clone())?;
+            if value.contains('\0') {
+                bail!("{location}: variable value contains a NUL character");
+            }
+            Ok(EnvOp::Set {
+                name: name.to_string(),
+                value,
+            })
+        })
+        .collect()
+}
+
+fn parse_env_value(value: &str) -> Result<String> {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        let Some(value) = quoted.strip_suffix('\'') else {
+            bail!("unterminated single quote");
+        };
+        return Ok(value.to_string());
+    }
+
+    let Some(quoted) = value.strip_prefix('"') else {
+        // Unquoted values may be followed by a comment
+        let value = value.split(" #").next().unwrap_or_default();
+        return Ok(value.trim_end().to_string());
+    };
+    let Some(quoted) = quoted.strip_suffix('"') else {
+        bail!("unterminated double quote");
+    };
+    let mut res = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some(c @ ('\\' | '"' | '$')) => res.push(c),
+            Some(c) => {
+                res.push('\\');
+                res.push(c);
+            }
+            None => bail!("trailing backslash"),
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::parse_env_file;
+
+    #[test]
+    fn invalid_names() {
+        let path = Path::new("test.env");
+        for name in ["A;rm -rf ~", "$(cmd)", "1ST", "A\0B", "FOO BAR"] {
+            let contents = format!("# comment\nGOOD=1\n{name}=value\n");
+            let err = parse_env_file(path, &contents).unwrap_err().to_string();
+            assert!(
+                err.starts_with("test.env:3: invalid variable name"),
+                "{err}"
+            );
+        }
+
+        let ops = parse_env_file(path, "export _GOOD_1='value'\n").unwrap();
+        assert_eq!(ops.len(), 1);
+    }
+}
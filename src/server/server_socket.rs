@@ -1,22 +1,59 @@
 use std::{
     fmt::Debug,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
 
-use anyhow::{anyhow, bail, Context, Ok, Result};
+use anyhow::{anyhow, Context, Ok, Result};
 use log::{trace, Level};
 
-use cargo_fixture::rpc_socket::{platform::*, ConnectionType, Request, Response, RpcSocket};
+use cargo_fixture::rpc_socket::{
+    platform::*, ConnectionType, ErrorCode, Request, Response, RpcSocket, CAPABILITIES,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+use crate::{
+    event_log::{self, Event},
+    utils::RmGuard,
+};
 
-use crate::utils::RmGuard;
+/// The host side of a connection.
+pub type Socket = RpcSocket<UnixStream>;
 
 #[derive(Debug)]
 pub struct ServerSocket {
     socket: UnixListener,
+    next_id: AtomicU64,
     /// Ensure socket file is removed on server shutdown
     _rm_guard: RmGuard<PathBuf>,
 }
 
+/// A freshly accepted connection, the handshake is yet to be performed.
+#[derive(Debug)]
+pub struct Incoming {
+    stream: UnixStream,
+    /// Identifies the connection in logs, unique within a session.
+    pub id: u64,
+}
+
+/// A connection whose `Hello` message has been accepted.
+///
+/// The handshake is completed by [`Connection::confirm()`], until then the client waits.
+/// This lets the host hold a test back until it's been scheduled.
+#[derive(Debug)]
+pub struct Connection {
+    pub socket: Socket,
+    pub conn_type: ConnectionType,
+    /// Identifies the connection in logs, unique within a session.
+    pub id: u64,
+    /// Name of the test, if provided by the client.
+    pub name: Option<String>,
+    /// Time limit requested by the test.
+    pub timeout: Option<Duration>,
+    protocol: u32,
+}
+
 impl ServerSocket {
     pub fn new(socket_path: &Path) -> Result<Self> {
         trace!("accepting connections on {}", socket_path.display());
@@ -25,42 +62,113 @@ impl ServerSocket {
             .with_context(|| format!("Could not create a socket at {}", socket_path.display()))?;
         Ok(Self {
             socket,
+            next_id: AtomicU64::new(0),
             _rm_guard: rm_guard,
         })
     }
 
-    pub async fn accept(&self) -> Result<(RpcSocket, ConnectionType)> {
-        let (socket, _addr) = self
+    pub async fn accept(&self) -> Result<Incoming> {
+        let (stream, _addr) = self
             .socket
             .accept()
             .await
             .context("Error accepting connection")?;
-        trace!("connection accepted, performing handshake...");
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        trace!("connection {id} accepted");
+        Ok(Incoming { stream, id })
+    }
+}
+
+impl Incoming {
+    /// Receive and check the client's `Hello` message.
+    pub async fn handshake(self) -> Result<Connection> {
+        let Self { stream, id } = self;
+        trace!("performing handshake with connection {id}...");
 
-        // Perform a connection handshake
-        let mut socket = RpcSocket::new(socket);
-        let our_ver = env!("CARGO_PKG_VERSION_MAJOR").parse::<u32>().unwrap();
+        let socket = RpcSocket::new(stream);
         let msg = socket
             .recv()
             .await?
             .ok_or_else(|| anyhow!("Connection closed before handshake"))?;
-        let connection_type = match msg {
+        let (connection_type, protocol, name, timeout) = match msg {
             Request::Hello {
                 version,
                 connection_type,
-            } if version == our_ver => connection_type,
-
-            Request::Hello {
-                version: theirs, ..
+                protocol,
+                min_protocol,
+                capabilities,
+                name,
+                timeout_ms,
             } => {
-                bail!("This cargo-fixture binary version ({our_ver}.x.y) is not compatible with the library linked by test code ({theirs}.x.y)")
+                if min_protocol > PROTOCOL_VERSION || protocol < MIN_PROTOCOL_VERSION {
+                    let msg = format!(
+                        "This cargo-fixture binary version ({}) supports protocol {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION}, \
+                        the library linked by test code ({version}.x.y) supports protocol {min_protocol}..={protocol}",
+                        env!("CARGO_PKG_VERSION")
+                    );
+                    return reject(&socket, ErrorCode::IncompatibleVersion, msg).await;
+                }
+                trace!("client version {version}.x.y, capabilities: {capabilities:?}");
+                (
+                    connection_type,
+                    protocol.min(PROTOCOL_VERSION),
+                    name,
+                    timeout_ms.map(Duration::from_millis),
+                )
             }
 
-            other => bail!("Expected Hello message, got {other:?}"),
+            other => {
+                let msg = format!("Expected Hello message, got {}", other.as_ref());
+                return reject(&socket, ErrorCode::UnexpectedRequest, msg).await;
+            }
         };
-        socket.send(Response::Ok).await?;
 
-        trace!("connection handshake ok ({connection_type:?})");
-        Ok((socket, connection_type))
+        trace!("connection hello ok ({connection_type:?}, id {id}, protocol {protocol}, name {name:?})");
+        event_log::emit(Event::Handshake {
+            conn: id,
+            connection_type,
+            name: name.as_deref(),
+        });
+
+        Ok(Connection {
+            socket,
+            conn_type: connection_type,
+            id,
+            name,
+            timeout,
+            protocol,
+        })
+    }
+}
+
+impl Connection {
+    /// Complete the handshake, letting the client proceed.
+    pub async fn confirm(&mut self) -> Result<()> {
+        let protocol = self.protocol;
+        if protocol >= 2 {
+            let capabilities = CAPABILITIES.to_vec();
+            self.socket
+                .send(Response::Hello {
+                    protocol,
+                    capabilities,
+                })
+                .await?;
+        } else {
+            self.socket.send(Response::Ok).await?;
+        }
+        self.socket.set_protocol(protocol);
+        Ok(())
+    }
+
+    /// Refuse the connection after its `Hello` message was accepted, e.g. because of an unexpected connection type.
+    pub async fn reject<T>(self, code: ErrorCode, msg: String) -> Result<T> {
+        reject(&self.socket, code, msg).await
     }
 }
+
+/// Let the client know why the handshake failed before hanging up.
+async fn reject<T>(socket: &Socket, code: ErrorCode, msg: String) -> Result<T> {
+    // The client may have already gone away, the error is reported either way
+    let _ = socket.send(Response::error(code, &msg)).await;
+    Err(anyhow!(msg))
+}
@@ -0,0 +1,144 @@
+//! The `cargo fixture status` subcommand, prints the state of running sessions.
+
+use std::{fmt::Write as _, fs, path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use log::debug;
+use smol::{future::FutureExt as _, Timer};
+use tabular::{row, Table};
+
+use cargo_fixture::rpc_socket::{
+    hello, platform::UnixStream, ConnectionType, EnvTarget, Request, Response, RpcSocket,
+    SessionStatus, TestStatus,
+};
+
+use crate::config::{Config, SOCKET_PREFIX, SOCKET_SUFFIX};
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn run(config: &Config) -> Result<()> {
+    let sockets = find_sockets(config)?;
+    let statuses = smol::block_on(async {
+        let mut statuses = vec![];
+        for path in sockets {
+            // A session that's shutting down may no longer be accepting connections
+            let timeout = async {
+                Timer::after(QUERY_TIMEOUT).await;
+                Err(anyhow!("timed out"))
+            };
+            match query(path.clone()).or(timeout).await {
+                Ok(status) => statuses.push(status),
+                // Most likely a leftover of a session that was killed
+                Err(err) => debug!("Could not query {}: {err:#}", path.display()),
+            }
+        }
+        statuses
+    });
+
+    if statuses.is_empty() {
+        bail!(
+            "No running cargo fixture session found in {}",
+            config.target_dir.display()
+        );
+    }
+
+    let output = statuses
+        .iter()
+        .map(format_status)
+        .collect::<Vec<_>>()
+        .join("\n");
+    print!("{output}");
+    Ok(())
+}
+
+fn find_sockets(config: &Config) -> Result<Vec<PathBuf>> {
+    let dir = fs::read_dir(&config.target_dir)
+        .with_context(|| format!("Could not read {}", config.target_dir.display()))?;
+    let mut sockets = dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| {
+                    name.starts_with(SOCKET_PREFIX) && name.ends_with(SOCKET_SUFFIX)
+                })
+        })
+        .collect::<Vec<_>>();
+    sockets.sort();
+    Ok(sockets)
+}
+
+async fn query(path: PathBuf) -> Result<SessionStatus> {
+    let stream = UnixStream::connect(&path).await?;
+    let socket = RpcSocket::connect_with(stream, hello(ConnectionType::Inspector)).await?;
+    match socket.call(Request::GetStatus).await? {
+        Response::Status(status) => Ok(*status),
+        other => Err(other.into_error().into()),
+    }
+}
+
+fn format_status(status: &SessionStatus) -> String {
+    let secs = |ms: u64| format!("{:.2}s", Duration::from_millis(ms).as_secs_f64());
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "cargo fixture session (PID {}, fixture `{}`)",
+        status.pid, status.fixture
+    );
+
+    out.push_str("Phases:\n");
+    let table = status
+        .phases
+        .iter()
+        .fold(Table::new("  {:<}  {:>}"), |table, phase| {
+            table.with_row(row!(&phase.phase, secs(phase.duration_ms)))
+        });
+    out.push_str(&table.to_string());
+
+    out.push_str("Env vars:\n");
+    if status.env.is_empty() {
+        out.push_str("  (none)\n");
+    }
+    for var in &status.env {
+        let target = match var.target {
+            EnvTarget::All => "",
+            EnvTarget::Build => " (build only)",
+            EnvTarget::Test => " (test run only)",
+        };
+        let _ = match &var.value {
+            Some(value) => writeln!(out, "  {}={value}{target}", var.name),
+            None => writeln!(out, "  {} unset{target}", var.name),
+        };
+    }
+
+    out.push_str("K-V store:\n");
+    if status.kv.is_empty() {
+        out.push_str("  (empty)\n");
+    }
+    for (key, value) in &status.kv {
+        let _ = writeln!(out, "  {key} = {value}");
+    }
+
+    let mut tests = |title: &str, tests: &[TestStatus], verb: &str| {
+        let _ = writeln!(out, "{title}:");
+        if tests.is_empty() {
+            out.push_str("  (none)\n");
+        }
+        for test in tests {
+            let _ = writeln!(
+                out,
+                "  `{}` (connection {}{}), {verb} for {}",
+                test.name.as_deref().unwrap_or("<unnamed>"),
+                test.conn,
+                if test.serial { ", serial" } else { "" },
+                secs(test.duration_ms),
+            );
+        }
+    };
+    tests("Connected tests", &status.tests, "connected");
+    tests("Waiting tests", &status.queue, "waiting");
+
+    out
+}
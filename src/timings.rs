@@ -0,0 +1,137 @@
+//! Measurement of the session phases and test connection stats, reported at exit.
+
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result};
+use serde::Serialize;
+use strum::AsRefStr;
+use tabular::{row, Table};
+
+use crate::event_log;
+
+#[derive(Serialize, AsRefStr, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Phase {
+    /// Building the fixture program.
+    Build,
+    /// Fixture program running until it calls `ready()`.
+    Setup,
+    /// Test command running.
+    Tests,
+    /// Waiting for the `--shell-on-failure` shell to exit or `--pause-before-cleanup` to be resumed.
+    Paused,
+    /// Fixture program running after tests finished.
+    Cleanup,
+}
+
+#[derive(Debug)]
+pub struct Timings {
+    start: Instant,
+    /// Phases entered so far along with their start times; a phase ends when the next one is entered.
+    phases: Mutex<Vec<(Phase, Instant)>>,
+    test_conns: AtomicUsize,
+    active_conns: AtomicUsize,
+    peak_conns: AtomicUsize,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            phases: Mutex::new(vec![]),
+            test_conns: AtomicUsize::new(0),
+            active_conns: AtomicUsize::new(0),
+            peak_conns: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Timings {
+    pub fn enter(&self, phase: Phase) {
+        self.phases.lock().unwrap().push((phase, Instant::now()));
+    }
+
+    /// Count a test connection as active until the returned guard is dropped.
+    pub fn test_conn(&self) -> ConnGuard<'_> {
+        self.test_conns.fetch_add(1, Ordering::Relaxed);
+        let active = self.active_conns.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_conns.fetch_max(active, Ordering::Relaxed);
+        ConnGuard(self)
+    }
+
+    pub fn report(&self) -> Report {
+        let now = Instant::now();
+        let phases = self.phases.lock().unwrap();
+        let ends = phases.iter().skip(1).map(|(_, start)| *start).chain([now]);
+        let phases = phases
+            .iter()
+            .zip(ends)
+            .map(|((phase, start), end)| PhaseReport {
+                phase: *phase,
+                duration_ms: event_log::millis(end - *start),
+            })
+            .collect();
+
+        Report {
+            phases,
+            total_ms: event_log::millis(now - self.start),
+            test_connections: self.test_conns.load(Ordering::Relaxed),
+            peak_concurrency: self.peak_conns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct ConnGuard<'a>(&'a Timings);
+
+impl<'a> Drop for ConnGuard<'a> {
+    fn drop(&mut self) {
+        self.0.active_conns.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub phases: Vec<PhaseReport>,
+    pub total_ms: u64,
+    pub test_connections: usize,
+    pub peak_concurrency: usize,
+}
+
+impl Report {
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+            .with_context(|| format!("Could not write timings to {}", path.display()))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |ms: u64| format!("{:.2}s", Duration::from_millis(ms).as_secs_f64());
+
+        let table = Table::new("  {:<}  {:>}");
+        let table = self.phases.iter().fold(table, |table, phase| {
+            table.with_row(row!(phase.phase.as_ref(), secs(phase.duration_ms)))
+        });
+        let table = table
+            .with_row(row!("total", secs(self.total_ms)))
+            .with_row(row!("test connections", self.test_connections))
+            .with_row(row!("peak concurrency", self.peak_concurrency));
+        write!(f, "{}", table.to_string().trim_end())
+    }
+}
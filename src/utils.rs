@@ -5,6 +5,11 @@ use std::{
     path::Path,
     pin::Pin,
     process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
     task::{self, Poll},
     time::{Duration, Instant},
 };
@@ -30,6 +35,9 @@ impl<T> ResultExt for Result<T> {
 pub trait CommandExt {
     fn display(&self) -> CommandPrint<'_>;
 
+    /// Like `display()`, but also prints the env vars set or removed for the command.
+    fn display_with_env(&self) -> CommandPrint<'_>;
+
     /// `SmolCommand::from()` won't take stdio config from `Command` (it can't),
     /// this function performs the conversion and sets up stdio.
     fn into_smol(self, stdin: Stdio, stdout: Stdio, stderr: Stdio) -> SmolCommand;
@@ -37,7 +45,17 @@ pub trait CommandExt {
 
 impl CommandExt for Command {
     fn display(&self) -> CommandPrint<'_> {
-        CommandPrint(self)
+        CommandPrint {
+            cmd: self,
+            env: false,
+        }
+    }
+
+    fn display_with_env(&self) -> CommandPrint<'_> {
+        CommandPrint {
+            cmd: self,
+            env: true,
+        }
     }
 
     fn into_smol(self, stdin: Stdio, stdout: Stdio, stderr: Stdio) -> SmolCommand {
@@ -47,12 +65,25 @@ impl CommandExt for Command {
     }
 }
 
-pub struct CommandPrint<'a>(&'a Command);
+pub struct CommandPrint<'a> {
+    cmd: &'a Command,
+    env: bool,
+}
 
 impl<'a> fmt::Display for CommandPrint<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0.get_program().to_string_lossy())?;
-        for arg in self.0.get_args() {
+        if self.env {
+            for (name, value) in self.cmd.get_envs() {
+                match value {
+                    Some(value) => {
+                        write!(f, "{}={} ", name.to_string_lossy(), value.to_string_lossy())?
+                    }
+                    None => write!(f, "-u {} ", name.to_string_lossy())?,
+                }
+            }
+        }
+        write!(f, "{}", self.cmd.get_program().to_string_lossy())?;
+        for arg in self.cmd.get_args() {
             write!(f, " {}", arg.to_string_lossy())?;
         }
         fmt::Result::Ok(())
@@ -137,6 +168,68 @@ pub fn ctrlc_2x() -> Result<CtrlC<2>> {
     CtrlC::new()
 }
 
+/// Subscribers notified of every SIGINT, the `ctrlc` crate only allows one handler per process.
+static CTRLC_SUBSCRIBERS: Mutex<Option<Vec<CtrlCSubscriber>>> = Mutex::new(None);
+
+/// Number of `CtrlCSuspended` guards alive.
+static CTRLC_2X_SUSPENDED: AtomicUsize = AtomicUsize::new(0);
+
+struct CtrlCSubscriber {
+    tx: channel::Sender<Instant>,
+    /// Whether SIGINTs are withheld while suspended, see `suspend_ctrlc_2x()`.
+    suspendable: bool,
+}
+
+fn ctrlc_subscribe(suspendable: bool) -> Result<channel::Receiver<Instant>> {
+    let (tx, rx) = channel::bounded(10);
+
+    let mut subscribers = CTRLC_SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_none() {
+        ctrlc::set_handler(|| {
+            let suspended = CTRLC_2X_SUSPENDED.load(Ordering::SeqCst) > 0;
+            if let Some(subscribers) = CTRLC_SUBSCRIBERS.lock().unwrap().as_mut() {
+                subscribers.retain(|sub| !sub.tx.is_closed());
+                for sub in subscribers.iter() {
+                    if !(suspended && sub.suspendable) {
+                        let _ = sub.tx.try_send(Instant::now());
+                    }
+                }
+            }
+        })
+        .context("Failed to set up SIGINT handler")?;
+    }
+    subscribers
+        .get_or_insert_with(Vec::new)
+        .push(CtrlCSubscriber { tx, suspendable });
+
+    Ok(rx)
+}
+
+/// Returns a future that resolves once a SIGINT is received, SIGINTs are caught from the time of the call.
+pub fn ctrlc_once() -> Result<impl Future<Output = ()>> {
+    let rx = ctrlc_subscribe(false)?;
+    Ok(async move {
+        let _ = rx.recv().await;
+    })
+}
+
+/// Make `ctrlc_2x()` futures ignore SIGINTs for as long as the returned guard is held.
+///
+/// This is used while an interactive program runs in the foreground, its user's Ctrl+Cs are meant for it.
+pub fn suspend_ctrlc_2x() -> CtrlCSuspended {
+    CTRLC_2X_SUSPENDED.fetch_add(1, Ordering::SeqCst);
+    CtrlCSuspended(())
+}
+
+#[must_use]
+pub struct CtrlCSuspended(());
+
+impl Drop for CtrlCSuspended {
+    fn drop(&mut self) {
+        CTRLC_2X_SUSPENDED.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 pub struct CtrlC<const N: usize> {
     rx: channel::Receiver<Instant>,
     num_successions: usize,
@@ -145,15 +238,8 @@ pub struct CtrlC<const N: usize> {
 
 impl<const N: usize> CtrlC<N> {
     pub fn new() -> Result<Self> {
-        let (tx, rx) = channel::bounded(10);
-
-        ctrlc::set_handler(move || {
-            let _ = tx.try_send(Instant::now());
-        })
-        .context("Failed to set up SIGINT handler")?;
-
         Ok(Self {
-            rx,
+            rx: ctrlc_subscribe(true)?,
             num_successions: 1,
             last_timestamp: Instant::now().checked_sub(Self::INTERVAL).unwrap(),
         })
@@ -191,3 +277,22 @@ impl<const N: usize> FusedFuture for CtrlC<N> {
         self.num_successions >= N
     }
 }
+
+/// A `Duration` parsed from a string such as `500ms`, `30s`, `5m` or `1h`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HumanDuration(pub Duration);
+
+#[derive(thiserror::Error, Debug)]
+#[error("expected a number with a unit, such as 500ms, 30s, 5m or 1h")]
+pub struct ParseDurationError;
+
+impl FromStr for HumanDuration {
+    type Err = ParseDurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The same syntax as the `timeout` argument of `with_fixture`
+        cargo_fixture::parse_duration_ms(s)
+            .map(|millis| Self(Duration::from_millis(millis)))
+            .ok_or(ParseDurationError)
+    }
+}
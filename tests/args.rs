@@ -1,5 +1,7 @@
+use std::fs;
+
 pub mod common;
-use common::cargo_fixture;
+use common::{cargo_fixture, tmp_path, RmGuard};
 
 #[test]
 fn args() {
@@ -165,3 +167,127 @@ fn args() {
 fn shell() {
     cargo_fixture().run_assert_shell();
 }
+
+#[test]
+fn dry_run() {
+    let output = cargo_fixture().run_dry_run(&[
+        "--fixture",
+        "fixture_args",
+        "-A",
+        "fixture-arg",
+        "--release",
+        "-j=1",
+        "--some-other-flag",
+        "--",
+        "--nocapture",
+    ]);
+    let lines = output.lines().map(str::trim).collect::<Vec<_>>();
+
+    let classified = |args: &str, dest: &str| {
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.strip_prefix(args).map(str::trim) == Some(dest)),
+            "`{args}` not classified as {dest}:\n{output}"
+        );
+    };
+    classified("--fixture fixture_args", "cargo fixture option");
+    classified("-A fixture-arg", "fixture program argument");
+    classified(
+        "--release",
+        "cargo option, passed to the fixture build and cargo test",
+    );
+    classified(
+        "-j=1",
+        "cargo option, passed to the fixture build and cargo test",
+    );
+    classified("--some-other-flag", "cargo test argument");
+    classified("--nocapture", "test binary argument");
+
+    let command = |title: &str| {
+        let pos = lines.iter().position(|line| *line == title).unwrap();
+        lines[pos + 1]
+    };
+    assert!(command("Fixture build command:")
+        .ends_with("test --release -j=1 --test fixture_args --no-run --features _fixture --message-format=json-render-diagnostics"));
+    assert!(command("Fixture run command:").ends_with("<fixture binary> fixture-arg"));
+    assert!(command("Fixture run command:").starts_with("CARGO_FIXTURE_SOCKET="));
+    assert!(command("Test command:")
+        .ends_with("test --features _fixture --release -j=1 --some-other-flag -- --nocapture"));
+}
+
+#[test]
+fn dry_run_no_fixture() {
+    let env_file = tmp_path("dry_run_no_fixture.env");
+    let _rm = RmGuard::new(env_file.clone());
+    fs::write(&env_file, "DB_URI=postgres://localhost\n").unwrap();
+
+    let output = cargo_fixture().run_dry_run(&[
+        "--no-fixture".as_ref(),
+        "--env-file".as_ref(),
+        env_file.as_os_str(),
+    ]);
+    let test_cmd = output
+        .lines()
+        .skip_while(|line| *line != "Test command:")
+        .nth(1)
+        .unwrap();
+    assert!(test_cmd.contains("DB_URI=postgres://localhost"), "{output}");
+    assert!(!output.contains("Fixture build command"), "{output}");
+}
+
+#[test]
+fn dry_run_bench_run() {
+    let output = cargo_fixture()
+        .arg("bench")
+        .run_dry_run(&["-p", "foo", "--bench", "db"]);
+    assert!(
+        output.lines().any(|line| {
+            line.trim().strip_prefix("--bench db").map(str::trim) == Some("cargo test argument")
+        }),
+        "{output}"
+    );
+    let test_cmd = output
+        .lines()
+        .skip_while(|line| *line != "Test command:")
+        .nth(1)
+        .unwrap();
+    assert!(test_cmd.ends_with("bench --features _fixture -p foo --bench db --"));
+
+    let output = cargo_fixture()
+        .arg("run")
+        .run_dry_run(&["--example=demo", "--", "--demo-arg"]);
+    let test_cmd = output
+        .lines()
+        .skip_while(|line| *line != "Test command:")
+        .nth(1)
+        .unwrap();
+    assert!(test_cmd.ends_with("run --features _fixture --example=demo -- --demo-arg"));
+}
+
+#[test]
+fn dry_run_coverage() {
+    let output = cargo_fixture().run_dry_run(&["--coverage"]);
+    let command = |title: &str| {
+        output
+            .lines()
+            .skip_while(|line| *line != title)
+            .nth(1)
+            .unwrap()
+            .to_string()
+    };
+
+    let build_cmd = command("Fixture build command:");
+    assert!(build_cmd.contains("-C instrument-coverage"), "{build_cmd}");
+    assert!(build_cmd.contains("CARGO_TARGET_DIR="), "{build_cmd}");
+    let run_cmd = command("Fixture run command:");
+    assert!(run_cmd.contains("fixture-%p-%m.profraw"), "{run_cmd}");
+    let test_build_cmd = command("Test build command:");
+    assert!(
+        test_build_cmd.contains("--message-format=json"),
+        "{test_build_cmd}"
+    );
+    let test_cmd = command("Test command:");
+    assert!(test_cmd.contains("-C instrument-coverage"), "{test_cmd}");
+    assert!(test_cmd.contains("LLVM_PROFILE_FILE="), "{test_cmd}");
+}
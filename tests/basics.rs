@@ -1,9 +1,17 @@
-use std::env;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
 
-use cargo_fixture::{with_fixture, TestClient};
+use cargo_fixture::{blocking, with_fixture, TestClient};
+use rstest::rstest;
+use test_case::test_case;
 
 pub mod common;
-use common::{cargo_fixture, confirm_callback_ran};
+use common::{cargo_fixture, confirm_callback_ran, tmp_path, RmGuard};
 
 use crate::common::KvExample;
 
@@ -16,6 +24,8 @@ use crate::common::KvExample;
 #[test]
 fn env_var() {
     cargo_fixture()
+        .env("UNSET_ME", "1")
+        .env("SOME_PATH", "/middle")
         .run_test("env_var")
         .output()
         .assert_success();
@@ -27,15 +37,99 @@ async fn env_var_callback(_client: TestClient) {
     assert_eq!(env::var("FOO").unwrap(), "foo");
     assert_eq!(env::var("BAR").unwrap(), "bar");
     assert_eq!(env::var("BAZ").unwrap(), "baz");
+    assert!(env::var_os("UNSET_ME").is_none());
+    let some_path = env::var_os("SOME_PATH").unwrap();
+    let some_path = env::split_paths(&some_path).collect::<Vec<_>>();
+    assert_eq!(some_path, ["/first", "/middle", "/last"].map(PathBuf::from));
+    assert_eq!(env::var("TEST_ONLY").unwrap(), "1");
+    assert!(env::var_os("BUILD_ONLY").is_none());
     confirm_callback_ran("env_var");
 }
 
+#[test]
+fn record_replay() {
+    for test_name in ["env_var", "kv"] {
+        let recording = tmp_path(format!("{test_name}.recording.jsonl"));
+        let _rm = RmGuard::new(recording.clone());
+
+        cargo_fixture()
+            .arg("--record")
+            .arg(&recording)
+            .env("UNSET_ME", "1")
+            .env("SOME_PATH", "/middle")
+            .run_test(test_name)
+            .output()
+            .assert_success();
+
+        // The callbacks verify the environment and K-V data are the same as when recording
+        let output = cargo_fixture()
+            .arg("--replay")
+            .arg(&recording)
+            .env("UNSET_ME", "1")
+            .env("SOME_PATH", "/middle")
+            .run_test(test_name)
+            .output();
+        output.assert_success();
+        assert!(!output.stderr().contains("building fixture program"));
+        assert!(output.stderr().contains("replaying fixture"));
+    }
+}
+
+#[test]
+fn no_fixture() {
+    let kv_file = tmp_path("no_fixture.kv.json");
+    let env_file = tmp_path("no_fixture.env");
+    let _rm = (
+        RmGuard::new(kv_file.clone()),
+        RmGuard::new(env_file.clone()),
+    );
+    fs::write(
+        &kv_file,
+        r#"{ "example": { "foo": "foo", "bar": "127.0.0.1" } }"#,
+    )
+    .unwrap();
+    fs::write(
+        &env_file,
+        "# staging\nexport FOO=foo\nQUOTED=\"a \\\"b\\\"\\nc\"\nSINGLE='$d'\n",
+    )
+    .unwrap();
+
+    let output = cargo_fixture()
+        .arg("--no-fixture")
+        .arg("--kv-file")
+        .arg(&kv_file)
+        .arg("--env-file")
+        .arg(&env_file)
+        .run_test("no_fixture")
+        .output();
+    output.assert_success();
+    assert!(!output.stderr().contains("building fixture program"));
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn no_fixture_callback(client: TestClient) {
+    assert_eq!(env::var("FOO").unwrap(), "foo");
+    assert_eq!(env::var("QUOTED").unwrap(), "a \"b\"\nc");
+    assert_eq!(env::var("SINGLE").unwrap(), "$d");
+    let example: KvExample = client.get_value("example").await.unwrap();
+    assert_eq!(example.bar.to_string(), "127.0.0.1");
+    confirm_callback_ran("no_fixture");
+}
+
 #[test]
 fn failing_test() {
-    cargo_fixture()
-        .run_test("failing_test")
-        .output()
-        .assert_error("thread 'failing_test_callback' panicked");
+    let output = cargo_fixture().run_test("failing_test").output();
+    output.assert_error("thread 'failing_test_callback'");
+    // Newer toolchains print the thread ID between the name and `panicked`, ie.
+    // `thread 'failing_test_callback' (1234) panicked at ...`
+    let stderr = output.stderr();
+    assert!(
+        stderr.lines().any(|line| {
+            line.starts_with("thread 'failing_test_callback'") && line.contains(" panicked at ")
+        }),
+        "panic message not found in stderr: {stderr}"
+    );
 }
 
 #[with_fixture]
@@ -49,15 +143,668 @@ fn kv() {
     cargo_fixture().run_test("kv").output().assert_success();
 }
 
+#[test]
+fn coverage() {
+    if !llvm_tools_installed() {
+        eprintln!("skipping, the llvm-tools rustup component is not installed");
+        return;
+    }
+
+    cargo_fixture()
+        .arg("--coverage")
+        .run_test("kv")
+        .output()
+        .assert_success();
+
+    // CARGO_TARGET_TMPDIR is <target dir>/tmp
+    let lcov = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .parent()
+        .unwrap()
+        .join("coverage/lcov.info");
+    let lcov = fs::read_to_string(&lcov).unwrap();
+    let test_source = Path::new("tests").join("basics.rs");
+    assert!(
+        lcov.lines()
+            .filter_map(|line| line.strip_prefix("SF:"))
+            .any(|source| Path::new(source).ends_with(&test_source)),
+        "{lcov}"
+    );
+}
+
+/// Whether the LLVM tools needed by `--coverage` are installed, see `llvm_tool()` in `src/coverage.rs`.
+fn llvm_tools_installed() -> bool {
+    let rustc = |args: &[&str]| {
+        let output = Command::new("rustc").args(args).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let sysroot = rustc(&["--print", "sysroot"]);
+    let version = rustc(&["-vV"]);
+    let host = version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap();
+    Path::new(sysroot.trim())
+        .join("lib/rustlib")
+        .join(host)
+        .join("bin/llvm-cov")
+        .with_extension(env::consts::EXE_EXTENSION)
+        .exists()
+}
+
 #[with_fixture]
 #[smol_potat::test]
-async fn kv_callback(mut client: TestClient) {
+async fn kv_callback(client: TestClient) {
     let example: KvExample = client.get_value("example").await.unwrap();
     assert_eq!(example.foo, "foo");
     assert_eq!(example.bar.to_string(), "127.0.0.1");
+
+    // Pipelined requests
+    let (example1, example2) = smol::future::zip(
+        client.get_value::<KvExample>("example"),
+        client.get_value::<KvExample>("example"),
+    )
+    .await;
+    assert_eq!(example1.unwrap().foo, "foo");
+    assert_eq!(example2.unwrap().foo, "foo");
+
     confirm_callback_ran("kv");
 }
 
+#[test]
+fn inject_params() {
+    cargo_fixture()
+        .run_test("inject_params")
+        .output()
+        .assert_success();
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn inject_params_callback(
+    client: TestClient,
+    #[kv("db_uri")] uri: String,
+    #[env("HTTP_PORT")] port: u16,
+) {
+    assert_eq!(uri, "postgres://localhost/test");
+    assert_eq!(port, 8080);
+    let uri: String = client.get_value("db_uri").await.unwrap();
+    assert_eq!(uri, "postgres://localhost/test");
+    confirm_callback_ran("inject_params");
+}
+
+#[test]
+fn inject_missing() {
+    cargo_fixture()
+        .run_test("inject_missing")
+        .output()
+        .assert_error("Could not get K-V value `missing`");
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn inject_missing_callback(#[kv("missing")] _value: String) {}
+
+#[test]
+fn parameterized() {
+    cargo_fixture()
+        .exact(false)
+        .run_test("parameterized")
+        .output()
+        .assert_success();
+}
+
+#[with_fixture]
+#[rstest]
+#[case(1, 10)]
+#[case(2, 20)]
+#[smol_potat::test]
+async fn parameterized_callback_rstest(
+    #[case] input: u32,
+    #[case] expected: u32,
+    #[kv("multiplier")] multiplier: u32,
+    client: TestClient,
+) {
+    assert_eq!(input * multiplier, expected);
+    let multiplier: u32 = client.get_value("multiplier").await.unwrap();
+    assert_eq!(input * multiplier, expected);
+    confirm_callback_ran("parameterized");
+}
+
+#[with_fixture]
+#[test_case(1, 10 ; "one")]
+#[test_case(2, 20 ; "two")]
+fn parameterized_callback_test_case(
+    input: u32,
+    expected: u32,
+    #[kv("multiplier")] multiplier: u32,
+) {
+    assert_eq!(input * multiplier, expected);
+}
+
+// A test attribute not recognized by its name
+use core::prelude::v1::test as check;
+
+#[with_fixture(test_attr_checked = false)]
+#[check]
+fn parameterized_callback_renamed_attr(#[kv("multiplier")] multiplier: u32) {
+    assert_eq!(multiplier, 10);
+}
+
+#[cfg(unix)]
+#[test]
+fn concurrent_handshake() {
+    cargo_fixture()
+        .run_test("concurrent_handshake")
+        .output()
+        .assert_success();
+}
+
+#[cfg(unix)]
+#[with_fixture]
+#[smol_potat::test]
+async fn concurrent_handshake_callback() {
+    // A connection that never sends its Hello message doesn't hold up others
+    let socket_path = cargo_fixture::socket_path().unwrap();
+    let _stalled = smol::net::unix::UnixStream::connect(socket_path)
+        .await
+        .unwrap();
+    let client = TestClient::connect(false).await.unwrap();
+    drop(client);
+    confirm_callback_ran("concurrent_handshake");
+}
+
+#[cfg(unix)]
+#[test]
+fn leaked_conn() {
+    let output = cargo_fixture().run_test("leaked_conn").output();
+    output.assert_success();
+    assert!(output
+        .stderr()
+        .contains("still open after the test command exited"));
+    assert!(output.stderr().contains("`leaked` (connection 2)"));
+
+    cargo_fixture()
+        .arg("--fail-on-leaks")
+        .run_test("leaked_conn")
+        .output()
+        .assert_error("Failing the test run because of leaked test connections");
+}
+
+#[cfg(unix)]
+#[with_fixture]
+#[test]
+fn leaked_conn_callback() {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        process::{Command, Stdio},
+    };
+
+    // Connect and hand the connection over to a process that outlives the test
+    let mut stream = UnixStream::connect(cargo_fixture::socket_path().unwrap()).unwrap();
+    let hello =
+        r#"{"msg":"Hello","data":{"version":1,"connection_type":"client","name":"leaked"}}"#;
+    writeln!(stream, "{hello}").unwrap();
+    let mut resp = String::new();
+    BufReader::new(&stream).read_line(&mut resp).unwrap();
+    assert!(resp.contains("Ok"), "{resp}");
+
+    #[allow(clippy::zombie_processes)] // The process is meant to outlive the test
+    Command::new("sleep")
+        .arg("3")
+        .stdin(Stdio::from(std::os::fd::OwnedFd::from(stream)))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    confirm_callback_ran("leaked_conn");
+}
+
+#[cfg(unix)]
+#[test]
+fn env_export() {
+    let wait_for_output = |path: &Path| loop {
+        match fs::read_to_string(path) {
+            Ok(output) if !output.is_empty() => break output,
+            _ => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+
+    let path = tmp_path("env_export.json");
+    let _rm = RmGuard::new(path.clone());
+    let child = cargo_fixture()
+        .arg("env")
+        .arg("--format=json")
+        .arg("--output")
+        .arg(&path)
+        .env("UNSET_ME", "1")
+        .spawn_fixture("env_var");
+    let output = wait_for_output(&path);
+    let result = child.interrupt();
+    result.assert_success();
+    assert!(result.stderr().contains("press Ctrl+C to let it clean up"));
+
+    let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let env = &output["env"];
+    assert_eq!(env["FOO"], "foo", "{output}");
+    assert_eq!(env["TEST_ONLY"], "1", "{output}");
+    assert!(env["UNSET_ME"].is_null(), "{output}");
+    assert!(env["CARGO_FIXTURE_SOCKET"].is_string(), "{output}");
+
+    let path = tmp_path("env_export.sh");
+    let _rm = RmGuard::new(path.clone());
+    let child = cargo_fixture()
+        .arg("env")
+        .arg("--output")
+        .arg(&path)
+        .env("UNSET_ME", "1")
+        .spawn_fixture("env_var");
+    let output = wait_for_output(&path);
+    child.interrupt().assert_success();
+    assert!(output.contains("export FOO='foo'\n"), "{output}");
+    assert!(output.contains("unset UNSET_ME\n"), "{output}");
+}
+
+#[test]
+fn secret() {
+    let event_log = tmp_path("secret.jsonl");
+    let _rm = RmGuard::new(event_log.clone());
+    let recording = tmp_path("secret.recording.jsonl");
+    let _rm_recording = RmGuard::new(recording.clone());
+
+    let output = cargo_fixture()
+        .arg("-L")
+        .arg("trace")
+        .arg("--event-log")
+        .arg(&event_log)
+        .arg("--record")
+        .arg(&recording)
+        .run_test("secret")
+        .output();
+    output.assert_success();
+    let stderr = output.stderr();
+    assert!(!stderr.contains("hunter"), "{stderr}");
+    let event_log = fs::read_to_string(&event_log).unwrap();
+    assert!(!event_log.contains("hunter"), "{event_log}");
+    assert!(event_log.contains("SECRET_VAR"), "{event_log}");
+
+    // Recordings keep the values so that they can be replayed, but the user is warned
+    assert!(stderr.contains("saved unredacted"), "{stderr}");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        let mode = fs::metadata(&recording).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn secret_callback(client: TestClient) {
+    // Tests get the actual values
+    assert_eq!(env::var("SECRET_VAR").unwrap(), "hunter2");
+    let value: String = client.get_value("secret_key").await.unwrap();
+    assert_eq!(value, "hunter3");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-fixture"))
+        .args(["fixture", "status"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("SECRET_VAR=***"), "{stdout}");
+    assert!(stdout.contains(r#"secret_key = "***""#), "{stdout}");
+    confirm_callback_ran("secret");
+}
+
+#[cfg(unix)]
+#[test]
+fn shell_on_failure() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // The shell runs with the fixture env, before the fixture cleans up
+    let shell = tmp_path("shell_on_failure.sh");
+    fs::write(&shell, "#!/bin/sh\necho \"failure shell: $FAILURE_VAR\"\n").unwrap();
+    fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+    let _rm = RmGuard::new(shell.clone());
+
+    let output = cargo_fixture()
+        .arg("--shell-on-failure")
+        .env("SHELL", &shell)
+        .run_test("shell_on_failure")
+        .output();
+    output.assert_error("thread 'shell_on_failure_callback'");
+    let stdout = output.stdout();
+    let shell_pos = stdout.find("failure shell: failure-value").unwrap();
+    let cleanup_pos = stdout.find("fixture cleanup").unwrap();
+    assert!(shell_pos < cleanup_pos, "{stdout}");
+
+    // A double Ctrl+C in the shell doesn't kill the fixture
+    fs::write(
+        &shell,
+        "#!/bin/sh\nkill -INT $PPID; kill -INT $PPID\nsleep 1\necho \"failure shell done\"\n",
+    )
+    .unwrap();
+    let output = cargo_fixture()
+        .arg("--shell-on-failure")
+        .env("SHELL", &shell)
+        .run_test("shell_on_failure")
+        .output();
+    output.assert_error("thread 'shell_on_failure_callback'");
+    let stdout = output.stdout();
+    assert!(stdout.contains("failure shell done"), "{stdout}");
+    assert!(stdout.contains("fixture cleanup"), "{stdout}");
+
+    // Stdin is null, so the pause ends right away
+    let output = cargo_fixture()
+        .arg("--pause-before-cleanup")
+        .run_test("shell_on_failure")
+        .output();
+    output.assert_error("press Enter or Ctrl+C to let the fixture clean up");
+    assert!(output.stdout().contains("fixture cleanup"));
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn shell_on_failure_callback(_client: TestClient) {
+    panic!();
+}
+
+#[test]
+fn status() {
+    cargo_fixture().run_test("status").output().assert_success();
+}
+
+#[with_fixture]
+#[test]
+fn status_callback() {
+    // The status subcommand is allowed from within a session
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cargo-fixture"))
+        .args(["fixture", "status"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout.contains("STATUS_VAR=status-value"), "{stdout}");
+    assert!(stdout.contains("status_key = 42"), "{stdout}");
+    assert!(
+        stdout.contains("`status_callback` (connection 1)"),
+        "{stdout}"
+    );
+    confirm_callback_ran("status");
+}
+
+#[test]
+fn timeout() {
+    // The test's own time limit takes precedence
+    cargo_fixture()
+        .arg("--test-conn-timeout")
+        .arg("1h")
+        .run_test("timeout")
+        .output()
+        .assert_error("Test `timeout_callback` (connection 1) exceeded its time limit of 500ms");
+}
+
+#[with_fixture(serial, timeout = "500ms")]
+#[smol_potat::test]
+async fn timeout_callback(_client: TestClient) {
+    smol::Timer::after(Duration::from_secs(3)).await;
+}
+
+#[test]
+fn test_conn_timeout() {
+    cargo_fixture()
+        .arg("--test-conn-timeout")
+        .arg("500ms")
+        .run_test("test_conn_timeout")
+        .output()
+        .assert_error("Test `test_conn_timeout_callback` (connection 1) exceeded its time limit");
+}
+
+#[with_fixture]
+#[test]
+fn test_conn_timeout_callback() {
+    thread::sleep(Duration::from_secs(3));
+}
+
+#[test]
+fn fixture_main() {
+    let output = cargo_fixture().run_test("fixture_main").output();
+    output.assert_success();
+    assert!(output
+        .stderr()
+        .contains("fixture_main cleanup, success: true"));
+
+    cargo_fixture()
+        .env("FIXTURE_MAIN_FAIL", "1")
+        .run_test("fixture_main")
+        .output()
+        .assert_error("Error: fixture setup failed: \"setup failed on purpose\"");
+}
+
+#[with_fixture]
+#[smol_potat::test]
+async fn fixture_main_callback(#[kv("example")] example: String) {
+    assert_eq!(example, "fixture_main");
+    confirm_callback_ran("fixture_main");
+}
+
+#[test]
+fn module() {
+    cargo_fixture()
+        .exact(false)
+        .run_test("module")
+        .output()
+        .assert_success();
+}
+
+#[with_fixture]
+mod module_callback {
+    use super::*;
+
+    #[smol_potat::test]
+    async fn with_client(client: TestClient) {
+        let example: KvExample = client.get_value("example").await.unwrap();
+        assert_eq!(example.foo, "foo");
+        confirm_callback_ran("module");
+    }
+
+    #[test]
+    fn blocking(#[kv("example")] example: KvExample) {
+        assert_eq!(example.bar.to_string(), "127.0.0.1");
+    }
+
+    mod nested {
+        use super::*;
+
+        #[smol_potat::test]
+        async fn nested(#[kv("example")] example: KvExample) {
+            assert_eq!(example.foo, "foo");
+        }
+    }
+
+    #[rstest]
+    #[case("foo")]
+    #[smol_potat::test]
+    async fn parameterized(#[case] foo: &str, #[kv("example")] example: KvExample) {
+        assert_eq!(example.foo, foo);
+    }
+
+    // Not ignored outside of cargo fixture
+    #[without_fixture]
+    #[test]
+    fn without_fixture() {}
+}
+
+#[test]
+fn blocking() {
+    cargo_fixture()
+        .run_test("blocking")
+        .output()
+        .assert_success();
+}
+
+#[with_fixture]
+#[test]
+fn blocking_callback(
+    #[kv("example")] example: KvExample,
+    #[env("BLOCKING")] blocking: String,
+    client: blocking::TestClient,
+) {
+    assert_eq!(blocking, "1");
+    assert_eq!(example.foo, "foo");
+    let example: KvExample = client.get_value("example").unwrap();
+    assert_eq!(example.bar.to_string(), "127.0.0.1");
+    confirm_callback_ran("blocking");
+}
+
+#[cfg(unix)]
+#[test]
+fn connect_with() {
+    cargo_fixture()
+        .run_test("connect_with")
+        .output()
+        .assert_success();
+}
+
+#[cfg(unix)]
+#[cfg_attr(not(feature = "_fixture"), ignore = "only ran under cargo fixture")]
+#[smol_potat::test]
+async fn connect_with_callback() {
+    let socket_path = cargo_fixture::socket_path().unwrap();
+    let stream = smol::net::unix::UnixStream::connect(socket_path)
+        .await
+        .unwrap();
+    let client = TestClient::connect_with(stream, false).await.unwrap();
+    let value: String = client.get_value("example").await.unwrap();
+    assert_eq!(value, "connect_with");
+    confirm_callback_ran("connect_with");
+}
+
+#[cfg(unix)]
+#[test]
+fn error_response() {
+    cargo_fixture()
+        .run_test("error_response")
+        .output()
+        .assert_success();
+}
+
+#[cfg(unix)]
+#[with_fixture]
+#[smol_potat::test]
+async fn error_response_callback(client: TestClient) {
+    use cargo_fixture::{
+        rpc_socket::{platform::UnixStream, ConnectionType, Request, Response, RpcSocket},
+        Error,
+    };
+
+    async fn hello(min_protocol: u32, protocol: u32) -> (RpcSocket<UnixStream>, Response) {
+        let path = env::var_os("CARGO_FIXTURE_SOCKET").unwrap();
+        let socket = RpcSocket::new(UnixStream::connect(path).await.unwrap());
+        let hello = Request::Hello {
+            version: 0,
+            connection_type: ConnectionType::Client,
+            protocol,
+            min_protocol,
+            capabilities: vec![],
+            name: None,
+            timeout_ms: None,
+        };
+        socket.send(hello).await.unwrap();
+        let resp = socket.recv().await.unwrap().unwrap();
+        (socket, resp)
+    }
+
+    let (_, resp) = hello(100, 101).await;
+    let err = resp.into_error();
+    assert!(matches!(err, Error::RpcIncompatibleVersion(_)), "{err:?}");
+
+    // A protocol 1 client is accepted regardless of its version
+    let (socket, resp) = hello(1, 1).await;
+    resp.as_ok().unwrap();
+    let set_env = Request::SetEnv {
+        name: "FOO".to_string(),
+        value: "foo".to_string(),
+    };
+    socket.send(set_env).await.unwrap();
+    let resp: Response = socket.recv().await.unwrap().unwrap();
+    let err = resp.into_error();
+    assert!(
+        matches!(&err, Error::RpcUnexpectedRequest(msg) if msg.contains("SetEnv")),
+        "{err:?}"
+    );
+
+    // Rejected connections don't affect others
+    let err = client.get_value::<String>("missing").await.unwrap_err();
+    assert!(matches!(err, Error::MissingKeyValue(_)), "{err:?}");
+
+    confirm_callback_ran("error_response");
+}
+
+#[test]
+fn event_log() {
+    let log_file = RmGuard::new(tmp_path("event_log.jsonl"));
+    cargo_fixture()
+        .arg("--event-log")
+        .arg(log_file.path())
+        .run_test("kv")
+        .output()
+        .assert_success();
+
+    let log = fs::read_to_string(log_file.path()).unwrap();
+    let events = log
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|record| {
+            assert!(record["ts"].is_u64(), "missing timestamp: {record}");
+            record["event"].as_str().unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+
+    for expected in [
+        "session-start",
+        "build-start",
+        "build-end",
+        "fixture-spawn",
+        "handshake",
+        "request",
+        "test-command",
+        "tests-finished",
+        "cleanup",
+        "session-end",
+    ] {
+        assert!(
+            events.iter().any(|e| e == expected),
+            "event `{expected}` missing in event log: {events:?}"
+        );
+    }
+    assert_eq!(events.first().unwrap(), "session-start");
+    assert_eq!(events.last().unwrap(), "session-end");
+}
+
+#[test]
+fn timings_json() {
+    let timings_file = RmGuard::new(tmp_path("timings.json"));
+    cargo_fixture()
+        .arg("--timings-json")
+        .arg(timings_file.path())
+        .run_test("kv")
+        .output()
+        .assert_success();
+
+    let timings = fs::read_to_string(timings_file.path()).unwrap();
+    let timings: serde_json::Value = serde_json::from_str(&timings).unwrap();
+    let phases = timings["phases"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|phase| phase["phase"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(phases, ["build", "setup", "tests", "cleanup"]);
+    assert_eq!(timings["test_connections"], 1);
+    assert_eq!(timings["peak_concurrency"], 1);
+}
+
 #[test]
 fn early_exit() {
     cargo_fixture()
@@ -66,6 +813,53 @@ fn early_exit() {
         .assert_error("fixture program exited without connecting to fixture");
 }
 
+#[cfg(unix)]
+#[test]
+fn exit_during_tests() {
+    use std::process;
+
+    let exit_file = RmGuard::new(tmp_path(format!("exit_during_tests_{}", process::id())));
+    let log_file = RmGuard::new(tmp_path(format!(
+        "exit_during_tests_{}.jsonl",
+        process::id()
+    )));
+    // The Ready response can't be delivered, output() checks the socket file was removed regardless
+    cargo_fixture()
+        .env("EXIT_FILE", exit_file.path())
+        .arg("--event-log")
+        .arg(log_file.path())
+        .run_test("exit_during_tests")
+        .output()
+        .assert_error("cargo fixture socket I/O error");
+
+    let log = fs::read_to_string(log_file.path()).unwrap();
+    let events = log
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|record| record["event"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        &events[events.len() - 2..],
+        ["cleanup", "session-end"],
+        "server not shut down: {events:?}"
+    );
+}
+
+#[cfg(unix)]
+#[with_fixture]
+#[smol_potat::test]
+async fn exit_during_tests_callback(_client: TestClient) {
+    use nix::{sys::signal::kill, unistd::Pid};
+    use std::time::Duration;
+
+    // Let the fixture exit and wait until cargo fixture reaps it
+    let pid: i32 = env::var("FIXTURE_PID").unwrap().parse().unwrap();
+    fs::write(env::var_os("EXIT_FILE").unwrap(), b"exit").unwrap();
+    while kill(Pid::from_raw(pid), None).is_ok() {
+        smol::Timer::after(Duration::from_millis(50)).await;
+    }
+}
+
 #[test]
 fn panic() {
     cargo_fixture()
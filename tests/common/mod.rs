@@ -76,6 +76,11 @@ impl CargoFixture {
         self
     }
 
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.cmd.arg(arg);
+        self
+    }
+
     pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
         self.cmd.env(key, value);
         self
@@ -108,9 +113,13 @@ impl CargoFixture {
 
             // Prepare the callback confirm file
             // - we need to know that cargo fixture actually called cargo such that
+            // The path is passed along as well, as the callback may be built
+            // with a different CARGO_TARGET_TMPDIR, e.g. with --coverage
+            let confirm_file = Self::confirm_filename(test_name);
             self.cmd
-                .env("CALLBACK_CONFIRM_ID", process::id().to_string());
-            Some(RmGuard::new(Self::confirm_filename(test_name)))
+                .env("CALLBACK_CONFIRM_ID", process::id().to_string())
+                .env("CALLBACK_CONFIRM_FILE", &confirm_file);
+            Some(RmGuard::new(confirm_file))
         } else {
             self.cmd.arg("--exec").args(&self.exec);
             None
@@ -127,6 +136,21 @@ impl CargoFixture {
         Child::new(child, confirm_file, self.check_socket_exists, self.exe_rm)
     }
 
+    /// Run cargo fixture with the fixture of the given test, without any test args.
+    pub fn spawn_fixture(mut self, test_name: &'static str) -> Child {
+        let fixture = format!("fixture_{test_name}");
+        self.cmd
+            .args(["-L", "debug", "--fixture", &fixture])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        eprintln!("running cargo fixture: {:?}", self.cmd);
+
+        let child = self.cmd.spawn().unwrap();
+        Child::new(child, None, self.check_socket_exists, self.exe_rm)
+    }
+
     #[track_caller]
     pub fn run_assert_args(
         mut self,
@@ -161,6 +185,20 @@ impl CargoFixture {
         );
     }
 
+    pub fn run_dry_run(mut self, args: &[impl AsRef<OsStr>]) -> String {
+        let output = self
+            .cmd
+            .arg("--dry-run")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
     pub fn run_assert_shell(mut self) {
         let print_args_exe = self.print_args_exe();
 
@@ -260,6 +298,16 @@ impl Child {
         self
     }
 
+    /// Send a single SIGINT, i.e. a Ctrl+C.
+    #[cfg(unix)]
+    pub fn interrupt(self) -> Output {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+
+        kill(Pid::from_raw(self.inner.id() as _), Some(Signal::SIGINT)).unwrap();
+        self.output()
+    }
+
     /// Send SIGINT repeatedly to kill stuck fixture.
     ///
     /// This is UNIX-only, as on Windows the Ctrl+C event can only be sent by process
@@ -279,7 +327,7 @@ impl Child {
                 use nix::unistd::Pid;
 
                 let pid = Pid::from_raw(pid as _);
-                while let Ok(_) = kill(pid, Some(Signal::SIGINT)) {
+                while kill(pid, Some(Signal::SIGINT)).is_ok() {
                     thread::sleep(Duration::from_millis(100));
                 }
             });
@@ -324,11 +372,19 @@ impl Output {
                 "It appears callback test didn't run (it didn't write CALLBACK_CONFIRM_ID to {})",
                 confirm_file.path().display()
             );
-            let id = fs::read_to_string(&confirm_file).expect(&err);
+            let id = fs::read_to_string(confirm_file).expect(&err);
             assert_eq!(id, process::id().to_string(), "{err}",);
         }
     }
 
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.inner.stdout).into_owned()
+    }
+
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.inner.stderr).into_owned()
+    }
+
     #[track_caller]
     pub fn assert_error(&self, substr: &str) {
         assert!(!self.inner.status.success());
@@ -341,7 +397,8 @@ impl Output {
 }
 
 pub fn confirm_callback_ran(test_name: &str) {
-    let confirm_file = CargoFixture::confirm_filename(test_name);
+    let confirm_file = env::var_os("CALLBACK_CONFIRM_FILE")
+        .map_or_else(|| CargoFixture::confirm_filename(test_name), PathBuf::from);
     let id = env::var("CALLBACK_CONFIRM_ID").unwrap();
     fs::write(&confirm_file, id.as_bytes()).unwrap();
 }
@@ -6,7 +6,7 @@ pub mod common;
 
 #[smol_potat::main]
 async fn main() {
-    let mut fixture = FixtureClient::connect().await.unwrap();
+    let fixture = FixtureClient::connect().await.unwrap();
     fixture.set_value("fixture-args", &[""; 0]).await.unwrap();
 
     let args = env::args().collect::<Vec<_>>();
@@ -0,0 +1,20 @@
+use std::net::IpAddr;
+
+use cargo_fixture::blocking::FixtureClient;
+
+mod common;
+use common::KvExample;
+
+fn main() {
+    let fixture = FixtureClient::connect().unwrap();
+
+    fixture.set_env_var("BLOCKING", "1").unwrap();
+    let example_value = KvExample {
+        foo: "foo".to_string(),
+        bar: IpAddr::from([127, 0, 0, 1]),
+    };
+    fixture.set_value("example", example_value).unwrap();
+
+    let success = fixture.ready().unwrap();
+    assert!(success);
+}
@@ -0,0 +1,7 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture.ready().await.unwrap();
+}
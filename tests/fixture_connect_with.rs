@@ -0,0 +1,13 @@
+use cargo_fixture::FixtureClient;
+use smol::net::unix::UnixStream;
+
+#[smol_potat::main]
+async fn main() {
+    // Any stream implementing the futures-io traits will do
+    let stream = UnixStream::connect(cargo_fixture::socket_path().unwrap())
+        .await
+        .unwrap();
+    let fixture = FixtureClient::connect_with(stream).await.unwrap();
+    fixture.set_value("example", "connect_with").await.unwrap();
+    fixture.ready().await.unwrap();
+}
@@ -1,12 +1,31 @@
-use cargo_fixture::FixtureClient;
+use cargo_fixture::{EnvOp, EnvTarget, FixtureClient};
 
 #[smol_potat::main]
 async fn main() {
-    let mut fixture = FixtureClient::connect().await.unwrap();
+    let fixture = FixtureClient::connect().await.unwrap();
     fixture.set_env_var("FOO", "foo").await.unwrap();
     fixture
         .set_env_vars([("BAR", "bar"), ("BAZ", "baz")])
         .await
         .unwrap();
+    fixture.unset_env_var("UNSET_ME").await.unwrap();
+    fixture
+        .prepend_path_var("SOME_PATH", "/first")
+        .await
+        .unwrap();
+    fixture.append_path_var("SOME_PATH", "/last").await.unwrap();
+
+    let set = |name: &str| EnvOp::Set {
+        name: name.to_string(),
+        value: "1".to_string(),
+    };
+    fixture
+        .update_env_vars_for(EnvTarget::Build, [set("BUILD_ONLY")])
+        .await
+        .unwrap();
+    fixture
+        .update_env_vars_for(EnvTarget::Test, [set("TEST_ONLY")])
+        .await
+        .unwrap();
     fixture.ready().await.unwrap();
 }
@@ -0,0 +1,24 @@
+use cargo_fixture::{Error, FixtureClient};
+use smol::future::{self, FutureExt as _};
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+
+    // Calls made while ready() is pending are rejected instead of hanging
+    let ready = fixture.ready();
+    futures_util::pin_mut!(ready);
+    assert!(future::poll_once(&mut ready).await.is_none());
+    let concurrent = async {
+        let err = fixture.set_value("example", 1).await.unwrap_err();
+        assert!(matches!(err, Error::RpcUnexpectedRequest(_)), "{err:?}");
+    };
+    concurrent
+        .or(async {
+            let _ = (&mut ready).await;
+            panic!("ready() returned before the concurrent call");
+        })
+        .await;
+
+    assert!(ready.await.unwrap());
+}
@@ -0,0 +1,23 @@
+use std::{env, path::PathBuf, process, thread, time::Duration};
+
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture
+        .set_env_var("FIXTURE_PID", process::id().to_string())
+        .await
+        .unwrap();
+
+    // Exit as soon as the test signals it's running, without waiting for the tests to finish
+    let exit_file = PathBuf::from(env::var_os("EXIT_FILE").expect("EXIT_FILE not set"));
+    thread::spawn(move || {
+        while !exit_file.exists() {
+            thread::sleep(Duration::from_millis(50));
+        }
+        process::exit(0);
+    });
+
+    fixture.ready().await.unwrap();
+}
@@ -0,0 +1,20 @@
+use std::env;
+
+use cargo_fixture::{Cleanup, FixtureClient};
+
+#[cargo_fixture::fixture_main]
+async fn main(fixture: &mut FixtureClient) -> Result<Cleanup, String> {
+    if env::var_os("FIXTURE_MAIN_FAIL").is_some() {
+        return Err("setup failed on purpose".to_string());
+    }
+
+    fixture
+        .set_value("example", "fixture_main")
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(Cleanup::new(|success| async move {
+        eprintln!("fixture_main cleanup, success: {success}");
+        Ok::<_, String>(())
+    }))
+}
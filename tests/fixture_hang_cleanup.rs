@@ -5,7 +5,7 @@ use common::fixture_hang;
 
 #[smol_potat::main]
 async fn main() {
-    let mut fixture = FixtureClient::connect().await.unwrap();
+    let fixture = FixtureClient::connect().await.unwrap();
     fixture.ready().await.unwrap();
     fixture_hang();
 }
@@ -0,0 +1,12 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture.set_env_var("HTTP_PORT", "8080").await.unwrap();
+    fixture
+        .set_value("db_uri", "postgres://localhost/test")
+        .await
+        .unwrap();
+    fixture.ready().await.unwrap();
+}
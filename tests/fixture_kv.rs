@@ -7,7 +7,7 @@ use common::KvExample;
 
 #[smol_potat::main]
 async fn main() {
-    let mut fixture = FixtureClient::connect().await.unwrap();
+    let fixture = FixtureClient::connect().await.unwrap();
 
     let example_value = KvExample {
         foo: "foo".to_string(),
@@ -0,0 +1,8 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture.set_value("multiplier", 10).await.unwrap();
+    fixture.ready().await.unwrap();
+}
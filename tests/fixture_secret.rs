@@ -0,0 +1,17 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture
+        .set_secret_env_var("SECRET_VAR", "hunter2")
+        .await
+        .unwrap();
+    // Stays a secret when overwritten without the flag
+    fixture.set_env_var("SECRET_VAR", "hunter2").await.unwrap();
+    fixture
+        .set_secret_value("secret_key", "hunter3")
+        .await
+        .unwrap();
+    fixture.ready().await.unwrap();
+}
@@ -2,6 +2,6 @@ use cargo_fixture::FixtureClient;
 
 #[smol_potat::main]
 async fn main() {
-    let mut fixture = FixtureClient::connect().await.unwrap();
+    let fixture = FixtureClient::connect().await.unwrap();
     fixture.ready().await.unwrap();
 }
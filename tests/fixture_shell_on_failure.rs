@@ -0,0 +1,13 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture
+        .set_env_var("FAILURE_VAR", "failure-value")
+        .await
+        .unwrap();
+    let success = fixture.ready().await.unwrap();
+    assert!(!success);
+    println!("fixture cleanup");
+}
@@ -0,0 +1,12 @@
+use cargo_fixture::FixtureClient;
+
+#[smol_potat::main]
+async fn main() {
+    let fixture = FixtureClient::connect().await.unwrap();
+    fixture
+        .set_env_var("STATUS_VAR", "status-value")
+        .await
+        .unwrap();
+    fixture.set_value("status_key", 42).await.unwrap();
+    fixture.ready().await.unwrap();
+}
@@ -1,4 +1,11 @@
-use std::{sync::Mutex, thread, time::Duration};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
 use cargo_fixture::{with_fixture, TestClient};
 
@@ -17,6 +24,8 @@ fn serial() {
 }
 
 static TEST_LOCK: Mutex<bool> = Mutex::new(true);
+static PARALLEL_RUNNING: AtomicUsize = AtomicUsize::new(0);
+static SERIAL_RUNNING: AtomicBool = AtomicBool::new(false);
 
 macro_rules! serial_callback {
     ($name:ident) => {
@@ -26,7 +35,14 @@ macro_rules! serial_callback {
             let mut lock = TEST_LOCK.try_lock().expect("serial test not serial");
             let confirm = *lock;
             *lock = false;
+            assert_eq!(
+                PARALLEL_RUNNING.load(Ordering::SeqCst),
+                0,
+                "serial test running alongside parallel ones"
+            );
+            SERIAL_RUNNING.store(true, Ordering::SeqCst);
             thread::sleep(Duration::from_millis(20));
+            SERIAL_RUNNING.store(false, Ordering::SeqCst);
 
             if confirm {
                 confirm_callback_ran("serial");
@@ -47,6 +63,23 @@ macro_rules! serial_callback_nonserial {
     };
 }
 
+macro_rules! serial_callback_parallel {
+    ($name:ident) => {
+        #[with_fixture]
+        #[smol_potat::test]
+        async fn $name(_client: TestClient) {
+            PARALLEL_RUNNING.fetch_add(1, Ordering::SeqCst);
+            let serial_running = SERIAL_RUNNING.load(Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            PARALLEL_RUNNING.fetch_sub(1, Ordering::SeqCst);
+            assert!(
+                !serial_running,
+                "parallel test running alongside a serial one"
+            );
+        }
+    };
+}
+
 serial_callback!(serial_callback_1);
 serial_callback!(serial_callback_2);
 serial_callback!(serial_callback_3);
@@ -64,3 +97,12 @@ serial_callback_nonserial!(serial_callback_nonserial_5);
 serial_callback_nonserial!(serial_callback_nonserial_6);
 serial_callback_nonserial!(serial_callback_nonserial_7);
 serial_callback_nonserial!(serial_callback_nonserial_8);
+
+serial_callback_parallel!(serial_callback_parallel_1);
+serial_callback_parallel!(serial_callback_parallel_2);
+serial_callback_parallel!(serial_callback_parallel_3);
+serial_callback_parallel!(serial_callback_parallel_4);
+serial_callback_parallel!(serial_callback_parallel_5);
+serial_callback_parallel!(serial_callback_parallel_6);
+serial_callback_parallel!(serial_callback_parallel_7);
+serial_callback_parallel!(serial_callback_parallel_8);